@@ -2,6 +2,8 @@ use criterion::{Criterion, criterion_group, criterion_main};
 
 use fhc::*;
 
+use std::path::PathBuf;
+
 const FILES: [&str; 11] = [
     "benches/bench.rs",
     "Cargo.toml",
@@ -17,11 +19,11 @@ const FILES: [&str; 11] = [
 ];
 
 fn sha256_single_file() {
-    let _result = file_sha256(&FILES[0]);
+    let _result = file_sha256(FILES[0]);
 }
 
 fn blake3_single_file() {
-    let _result = file_blake3(&FILES[0]);
+    let _result = file_blake3(FILES[0]);
 }
 
 fn sha256_seq_for_loop() {
@@ -64,6 +66,49 @@ fn blake3_rayon_par_iter() {
     let _results = rayon_par_iter(&FILES, Hash::Blake3);
 }
 
+fn sha256_per_device() {
+    let _results = per_device(&FILES, Hash::Sha256);
+}
+
+fn blake3_per_device() {
+    let _results = per_device(&FILES, Hash::Blake3);
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+fn sha256_io_uring() {
+    let _results = io_uring(&FILES, Hash::Sha256);
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+fn blake3_io_uring() {
+    let _results = io_uring(&FILES, Hash::Blake3);
+}
+
+/**
+Create a mix of many small files followed by one huge file (the worst case for input order)
+
+Files are created once under `target/bench-data`.
+*/
+fn mixed_sizes() -> Vec<PathBuf> {
+    let dir = PathBuf::from("target/bench-data");
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut files = vec![];
+    for i in 0..256 {
+        files.push((dir.join(format!("small-{i}.bin")), 64 * 1024));
+    }
+    files.push((dir.join("huge.bin"), 256 * 1024 * 1024));
+    files
+        .into_iter()
+        .map(|(file, size)| {
+            if std::fs::metadata(&file).ok().map(|m| m.len()) != Some(size) {
+                let data = (0..size).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+                std::fs::write(&file, data).unwrap();
+            }
+            file
+        })
+        .collect()
+}
+
 fn bench(c: &mut Criterion) {
     {
         let mut group = c.benchmark_group("SingleFile");
@@ -78,11 +123,35 @@ fn bench(c: &mut Criterion) {
         group.bench_function("Threading/Sha256", |b| b.iter(sha256_threading));
         group.bench_function("Messaging/Sha256", |b| b.iter(sha256_messaging));
         group.bench_function("RayonParIter/Sha256", |b| b.iter(sha256_rayon_par_iter));
+        group.bench_function("PerDevice/Sha256", |b| b.iter(sha256_per_device));
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        group.bench_function("IoUring/Sha256", |b| b.iter(sha256_io_uring));
         group.bench_function("SequentialForLoop/Blake3", |b| b.iter(blake3_seq_for_loop));
         group.bench_function("SequentialIter/Blake3", |b| b.iter(blake3_seq_iter));
         group.bench_function("Threading/Blake3", |b| b.iter(blake3_threading));
         group.bench_function("Messaging/Blake3", |b| b.iter(blake3_messaging));
         group.bench_function("RayonParIter/Blake3", |b| b.iter(blake3_rayon_par_iter));
+        group.bench_function("PerDevice/Blake3", |b| b.iter(blake3_per_device));
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        group.bench_function("IoUring/Blake3", |b| b.iter(blake3_io_uring));
+    }
+
+    {
+        let files = mixed_sizes();
+        let mut group = c.benchmark_group("MixedSizes");
+        group.sample_size(10);
+        group.bench_function("RayonParIter/Blake3", |b| {
+            b.iter(|| rayon_par_iter(&files, Hash::Blake3))
+        });
+        group.bench_function("LargestFirst/Blake3", |b| {
+            b.iter(|| largest_first(&files, Hash::Blake3))
+        });
+        group.bench_function("RayonParIter/Blake3Sha256", |b| {
+            b.iter(|| rayon_par_iter(&files, Hash::Blake3Sha256))
+        });
+        group.bench_function("LargestFirst/Blake3Sha256", |b| {
+            b.iter(|| largest_first(&files, Hash::Blake3Sha256))
+        });
     }
 }
 
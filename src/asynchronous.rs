@@ -0,0 +1,112 @@
+use {
+    crate::{Hash, Hasher, Options},
+    anyhow::Result,
+    std::path::{Path, PathBuf},
+    tokio::{
+        fs::File,
+        io::{AsyncRead, AsyncReadExt},
+        task::spawn_blocking,
+    },
+};
+
+/// Amount of input collected before handing it to a blocking thread for hashing
+const ASYNC_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Inputs smaller than this are hashed on the runtime thread
+const ASYNC_INLINE_SIZE: usize = 64 * 1024;
+
+impl Hash {
+    /**
+    Hash an async reader and return the hash(es) as `(ckfile, hash)` tuples
+
+    Input is read on the runtime in chunks of up to 1 MiB and each full chunk is hashed on the
+    blocking thread pool via [`spawn_blocking`], so large streams (e.g. uploads) do not stall
+    the runtime.
+    The `file` argument is only used to name the hash file(s).
+
+    # Errors
+
+    Returns an error if not able to read from the given reader
+    */
+    pub async fn hash_reader_async<R, P>(
+        &self,
+        mut reader: R,
+        file: P,
+    ) -> Result<Vec<(String, String)>>
+    where
+        R: AsyncRead + Unpin,
+        P: AsRef<Path>,
+    {
+        let mut hasher = Hasher::new(*self);
+        let mut buffer = vec![0; ASYNC_BUFFER_SIZE];
+        let mut len = 0;
+        loop {
+            let bytes_read = reader.read(&mut buffer[len..]).await?;
+            len += bytes_read;
+            if bytes_read == 0 && len < ASYNC_INLINE_SIZE {
+                hasher.update(&buffer[..len]);
+                break;
+            }
+            if bytes_read == 0 || len == buffer.len() {
+                (hasher, buffer) = spawn_blocking(move || {
+                    hasher.update(&buffer[..len]);
+                    (hasher, buffer)
+                })
+                .await?;
+                if bytes_read == 0 {
+                    break;
+                }
+                len = 0;
+            }
+        }
+        hasher.finalize(file)
+    }
+
+    /**
+    Hash a file asynchronously and return the hash(es) as `(ckfile, hash)` tuples
+
+    # Errors
+
+    Returns an error if not able to read the given file
+    */
+    pub async fn hash_file_async<P: AsRef<Path>>(&self, file: P) -> Result<Vec<(String, String)>> {
+        let file = file.as_ref();
+        self.hash_reader_async(File::open(file).await?, file).await
+    }
+
+    /**
+    Process a file asynchronously
+
+    Same as [`Hash::process_file`], but reads the file via [`Hash::hash_file_async`] and accesses
+    the hash file(s) on the blocking thread pool.
+
+    # Errors
+
+    Returns an error if not able to process the given file
+    */
+    pub async fn process_file_async<P: AsRef<Path>>(&self, file: P) -> Result<String> {
+        Options::from(*self).process_file_async(file).await
+    }
+}
+
+impl Options {
+    /**
+    Process a file asynchronously
+
+    Same as [`Options::process_file`], but reads the file via [`Hash::hash_file_async`] and
+    accesses the hash file(s) on the blocking thread pool.
+
+    # Errors
+
+    Returns an error if not able to process the given file
+    */
+    pub async fn process_file_async<P: AsRef<Path>>(&self, file: P) -> Result<String> {
+        let file = PathBuf::from(file.as_ref());
+
+        // Calculate the hashes
+        let hashes = self.hash.hash_file_async(&file).await?;
+
+        let options = self.clone();
+        spawn_blocking(move || options.check_or_save(&file, &hashes)).await?
+    }
+}
@@ -1,28 +1,294 @@
 use {
-    clap::{CommandFactory, Parser},
+    clap::{CommandFactory, Parser, Subcommand},
     clap_cargo::style::CLAP_STYLING,
-    fhc::{Hash, ProcessOption},
-    std::path::PathBuf,
+    fhc::{
+        Database, Encoding, Format, Hash, Link, Manifest, Options, ProcessOption, check_list,
+        dedupe, diff, diff_manifest, duplicates_json, duplicates_text, find_duplicates,
+        thread_pool,
+    },
+    rayon::ThreadPool,
+    std::path::{Path, PathBuf},
 };
 
+#[cfg(feature = "bao")]
+use fhc::{Algorithm, verify_slice};
+
+#[cfg(feature = "minisign")]
+use fhc::{
+    minisign::{PublicKey, SecretKey},
+    sign_minisign, verify_minisign,
+};
+
+#[cfg(feature = "openpgp")]
+use fhc::{check_list_openpgp, load_keyring};
+
+#[cfg(target_os = "linux")]
+use fhc::{Action, Watcher};
+
+#[cfg(feature = "ssh")]
+use fhc::{AllowedSigner, check_list_ssh, parse_allowed_signers, sign_ssh, ssh_key::PrivateKey};
+
 #[derive(Parser)]
-#[command(about, version, max_term_width = 80, styles = CLAP_STYLING)]
+#[allow(clippy::struct_excessive_bools)]
+#[command(
+    about,
+    version,
+    max_term_width = 80,
+    styles = CLAP_STYLING,
+    args_conflicts_with_subcommands = true
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Algorithm
-    #[arg(short, default_value = "blake3")]
+    #[arg(short, global = true, default_value = "blake3")]
     algorithm: Hash,
 
     /// Approach for processing multiple files
-    #[arg(short, long, default_value = "rayon-par-iter")]
+    #[arg(short, long, global = true, default_value = "rayon-par-iter")]
     process: ProcessOption,
 
+    /// Number of Rayon threads (default: one per logical CPU), used by the `rayon-par-iter`,
+    /// `per-device`, `largest-first`, and `io-uring` approaches, `-t`, `-c`, `-m`, `diff`,
+    /// `dupes`, and `db` (`sequential-*`, `threading`, and `messaging` ignore it)
+    #[arg(short, long, global = true)]
+    jobs: Option<usize>,
+
+    /// Format of new hash files
+    #[arg(short, long, default_value = "gnu")]
+    format: Format,
+
+    /// Digest encoding of new hash files and printed hashes
+    #[arg(short, long, default_value = "hex")]
+    encoding: Encoding,
+
+    /// Verify the files listed in checksum list file(s) instead
+    #[arg(short, long)]
+    check: bool,
+
+    /// Print a manifest of the files instead of writing hash files
+    #[arg(short, long, conflicts_with = "check")]
+    manifest: Option<Manifest>,
+
+    /// Record per-chunk digests of this size (e.g. `64M`) in new hash files to report corrupted
+    /// byte ranges when verification fails
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        conflicts_with_all = ["check", "manifest", "tree"]
+    )]
+    chunk_size: Option<u64>,
+
+    /// Record each file's size and mtime in new hash files to report failed verifications as
+    /// `FAILED (modified)` or `FAILED (possible corruption)` (content changed, metadata did not)
+    #[arg(long, conflicts_with_all = ["check", "manifest", "tree"])]
+    bit_rot: bool,
+
+    /// Also write a Bao outboard encoding (`.obao`) of each file for verified streaming
+    #[cfg(feature = "bao")]
+    #[arg(long, conflicts_with_all = ["check", "manifest", "tree"])]
+    bao: bool,
+
+    /// Sign new hash files with this minisign secret key (`.minisig`)
+    #[cfg(feature = "minisign")]
+    #[arg(long, value_name = "SECKEY", conflicts_with_all = ["check", "manifest"])]
+    sign_key: Option<PathBuf>,
+
+    /// Refuse hash files and checksum lists without a valid signature by this minisign public key
+    #[cfg(feature = "minisign")]
+    #[arg(long, value_name = "PUBKEY", conflicts_with = "manifest")]
+    trusted_key: Option<PathBuf>,
+
+    /// Verify the OpenPGP signature of each checksum list (clearsigned or detached `.gpg`,
+    /// `.sig`, `.sign`, or `.asc`) with the keys of this keyring before trusting it
+    #[cfg(feature = "openpgp")]
+    #[arg(long, value_name = "FILE", requires = "check")]
+    keyring: Option<PathBuf>,
+
+    /// Verify the SSH signature (`.sig`) of each checksum list with the keys of this
+    /// `allowed_signers` file before trusting it
+    #[cfg(feature = "ssh")]
+    #[arg(long, value_name = "FILE", requires = "check")]
+    allowed_signers: Option<PathBuf>,
+
+    /// Principal that must have made the SSH signatures (default: any allowed signer)
+    #[cfg(feature = "ssh")]
+    #[arg(long, requires = "allowed_signers")]
+    identity: Option<String>,
+
+    /// Namespace of the SSH signatures
+    #[cfg(feature = "ssh")]
+    #[arg(long, default_value = "file")]
+    namespace: String,
+
+    /// Trust digests cached in `user.fhc.*` extended attributes for files whose size, mtime,
+    /// ctime, and inode are unchanged, and cache new digests
+    #[cfg(all(unix, feature = "xattr"))]
+    #[arg(long, conflicts_with_all = ["check", "manifest", "tree"])]
+    cache: bool,
+
+    /// Ignore cached digests and re-hash every file (still updating the cache)
+    #[cfg(all(unix, feature = "xattr"))]
+    #[arg(long, requires = "cache")]
+    paranoid: bool,
+
+    /// Hash each directory as a whole (tree digest) with an adjacent hash file
+    #[arg(short, long, conflicts_with_all = ["check", "manifest"])]
+    tree: bool,
+
+    /// Include permission bits in tree digests
+    #[arg(long, requires = "tree")]
+    modes: bool,
+
     /// File(s)
     files: Vec<PathBuf>,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Compare two directory trees (or a tree and a saved manifest) by content
+    Diff {
+        /// First directory
+        a: PathBuf,
+
+        /// Second directory
+        #[arg(required_unless_present = "manifest")]
+        b: Option<PathBuf>,
+
+        /// Compare against a manifest (hashdeep, mtree, or checksum list) with paths relative to
+        /// the tree's root instead of a second directory
+        #[arg(short, long, conflicts_with = "b")]
+        manifest: Option<PathBuf>,
+    },
+
+    /// Find files with identical content
+    Dupes {
+        /// Files and/or directories
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+
+        /// Replace each duplicate with a link to the first file of its set
+        #[arg(long, value_name = "LINK")]
+        dedupe: Option<Link>,
+    },
+
+    /// Track files in a persistent hash database for incremental scans
+    Db {
+        /// Database file
+        #[arg(short, long, default_value = ".fhc.db")]
+        database: PathBuf,
+
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+
+    /// Hash new files (saving hash files) and re-verify modified files in directory trees as they
+    /// are written (Linux inotify)
+    #[cfg(target_os = "linux")]
+    Watch {
+        /// Print JSON Lines instead of text
+        #[arg(long)]
+        json: bool,
+
+        /// Directories
+        #[arg(required = true)]
+        dirs: Vec<PathBuf>,
+    },
+
+    /// Verify a byte range of a file against its `.b3` hash file via its Bao outboard
+    #[cfg(feature = "bao")]
+    VerifySlice {
+        /// File (with adjacent `.b3` and `.obao` files)
+        file: PathBuf,
+
+        /// Offset of the first byte
+        start: u64,
+
+        /// Number of bytes
+        len: u64,
+    },
+
+    /// Sign hash files, checksum lists, or manifests with a minisign secret key (`.minisig`)
+    #[cfg(feature = "minisign")]
+    Sign {
+        /// Secret key file (prompts for its password if encrypted)
+        #[arg(short, long, value_name = "SECKEY")]
+        key: PathBuf,
+
+        /// File(s)
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Sign checksum lists or manifests with an SSH private key (`.sig`, as `ssh-keygen -Y sign`)
+    #[cfg(feature = "ssh")]
+    SshSign {
+        /// Private key file (prompts for its passphrase if encrypted)
+        #[arg(short, long, value_name = "KEY")]
+        key: PathBuf,
+
+        /// Namespace
+        #[arg(short, long, default_value = "file")]
+        namespace: String,
+
+        /// File(s)
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Hash and record new and modified files (by size and mtime) and record deleted files
+    Scan {
+        /// Also re-hash unchanged files and verify them against the database
+        #[arg(long)]
+        full: bool,
+
+        /// Files and/or directories
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// List files new, modified, or deleted since the last scan (nothing is hashed or recorded)
+    Status {
+        /// Files and/or directories
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Print the history of all files (or the given files and directories)
+    Export {
+        /// Print JSON Lines instead of text
+        #[arg(long)]
+        json: bool,
+
+        /// Files and/or directories
+        paths: Vec<PathBuf>,
+    },
+}
+
 fn main() {
     let cli = Cli::parse();
 
+    let pool = cli
+        .jobs
+        .map(thread_pool)
+        .transpose()
+        .unwrap_or_else(|e| fail(&e));
+    let pool = pool.as_ref();
+
+    if let Some(command) = &cli.command {
+        run_command(&cli, command, pool);
+        return;
+    }
+
     // Print help if no files or arguments
     if cli.files.is_empty() {
         let mut cmd = Cli::command();
@@ -37,10 +303,340 @@ fn main() {
         cli.process.clone()
     };
 
-    for result in process.run(&cli.files, cli.algorithm) {
+    if let Some(manifest) = cli.manifest {
+        match install(pool, || manifest.generate(&cli.files, cli.algorithm)) {
+            Ok(manifest) => print!("{manifest}"),
+            Err(e) => fail(&e),
+        }
+        return;
+    }
+
+    #[cfg(feature = "bao")]
+    if cli.bao && !cli.algorithm.algorithms().contains(&Algorithm::Blake3) {
+        fail(&anyhow::anyhow!("--bao requires a BLAKE3 algorithm"));
+    }
+
+    let options = options(&cli);
+
+    let results = if cli.tree {
+        install(pool, || {
+            cli.files
+                .iter()
+                .map(|dir| options.process_tree(dir, cli.modes))
+                .collect::<Vec<_>>()
+        })
+    } else if cli.check {
+        install(pool, || check_lists(&cli, &options))
+    } else if let Some(pool) = pool {
+        process.run_in(&cli.files, options, pool)
+    } else {
+        process.run(&cli.files, options)
+    };
+
+    let mut failed = false;
+    for result in results {
         match result {
             Ok(result) => println!("{result}"),
-            Err(e) => eprintln!("ERROR: {e}"),
+            Err(e) => {
+                eprintln!("ERROR: {e}");
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Run a subcommand
+fn run_command(cli: &Cli, command: &Command, pool: Option<&ThreadPool>) {
+    match command {
+        Command::Diff { a, b, manifest } => {
+            run_diff(cli, a, b.as_ref(), manifest.as_ref(), pool);
+        }
+        Command::Dupes {
+            paths,
+            json,
+            dedupe,
+        } => {
+            run_dupes(paths, *json, *dedupe, pool);
+        }
+        Command::Db { database, command } => {
+            install(pool, || run_db(cli, database, command));
+        }
+        #[cfg(target_os = "linux")]
+        Command::Watch { json, dirs } => run_watch(cli, dirs, *json),
+        #[cfg(feature = "bao")]
+        Command::VerifySlice { file, start, len } => {
+            let status = Hash::Blake3
+                .expected(file)
+                .and_then(|hashes| verify_slice(file, &hashes[0].1, *start, *len))
+                .unwrap_or_else(|e| fail(&anyhow::anyhow!("{}: {e}", file.display())));
+            println!("{} [{start}+{len}]: {status}", file.display());
+        }
+        #[cfg(feature = "minisign")]
+        Command::Sign { key, files } => {
+            let key = secret_key(key);
+            for file in files {
+                match sign_minisign(file, &key) {
+                    Ok(signature) => println!("{signature}"),
+                    Err(e) => eprintln!("ERROR: {}: {e}", file.display()),
+                }
+            }
+        }
+        #[cfg(feature = "ssh")]
+        Command::SshSign {
+            key,
+            namespace,
+            files,
+        } => {
+            let key = ssh_private_key(key);
+            for file in files {
+                match sign_ssh(file, &key, namespace) {
+                    Ok(signature) => println!("{signature}"),
+                    Err(e) => eprintln!("ERROR: {}: {e}", file.display()),
+                }
+            }
+        }
+    }
+}
+
+/// Compare two trees or a tree and a manifest
+fn run_diff(
+    cli: &Cli,
+    a: &Path,
+    b: Option<&PathBuf>,
+    manifest: Option<&PathBuf>,
+    pool: Option<&ThreadPool>,
+) {
+    let results = install(pool, || match (b, manifest) {
+        (_, Some(manifest)) => diff_manifest(a, manifest, &cli.process),
+        (Some(b), None) => diff(a, b, &cli.process, cli.algorithm),
+        (None, None) => unreachable!(),
+    })
+    .unwrap_or_else(|e| fail(&e));
+
+    for (file, difference) in results {
+        match difference {
+            Ok(difference) => println!("{}: {difference}", file.display()),
+            Err(e) => eprintln!("ERROR: {}: {e}", file.display()),
+        }
+    }
+}
+
+/// Report duplicates and optionally replace them with links
+fn run_dupes(paths: &[PathBuf], json: bool, link: Option<Link>, pool: Option<&ThreadPool>) {
+    let sets = install(pool, || find_duplicates(paths)).unwrap_or_else(|e| fail(&e));
+
+    if json {
+        println!("{}", duplicates_json(&sets));
+    } else {
+        print!("{}", duplicates_text(&sets));
+    }
+
+    if let Some(link) = link {
+        for result in sets.iter().flat_map(|set| dedupe(set, link)) {
+            match result {
+                Ok(result) => eprintln!("{result}"),
+                Err(e) => eprintln!("ERROR: {e}"),
+            }
+        }
+    }
+}
+
+/// Get the options for processing files
+fn options(cli: &Cli) -> Options {
+    Options {
+        hash: cli.algorithm,
+        format: cli.format,
+        encoding: cli.encoding,
+        dry_run: false,
+        chunk_size: cli.chunk_size,
+        bit_rot: cli.bit_rot,
+        #[cfg(feature = "minisign")]
+        signing_key: cli.sign_key.as_deref().map(secret_key),
+        #[cfg(feature = "minisign")]
+        trusted_key: cli.trusted_key.as_deref().map(public_key),
+        #[cfg(feature = "bao")]
+        bao: cli.bao,
+        #[cfg(all(unix, feature = "xattr"))]
+        cache: cli.cache,
+        #[cfg(all(unix, feature = "xattr"))]
+        paranoid: cli.paranoid,
+    }
+}
+
+/// Hash new files and re-verify modified files in directory trees as they change
+#[cfg(target_os = "linux")]
+fn run_watch(cli: &Cli, dirs: &[PathBuf], json: bool) {
+    let options = options(cli);
+    let mut watcher = Watcher::new(dirs).unwrap_or_else(|e| fail(&e));
+    watcher
+        .run(&options, |event| {
+            if json {
+                println!("{}", event.json());
+            } else {
+                match (&event.result, event.action) {
+                    (Ok(result), _) => println!("{result}"),
+                    (Err(e), Action::Watched | Action::Overflowed) => eprintln!("ERROR: {e}"),
+                    (Err(e), _) => eprintln!("ERROR: {}: {e}", event.file.display()),
+                }
+            }
+            true
+        })
+        .unwrap_or_else(|e| fail(&e));
+}
+
+/// Scan, compare, or export a hash database
+fn run_db(cli: &Cli, database: &Path, command: &DbCommand) {
+    let mut database = Database::open(database).unwrap_or_else(|e| fail(&e));
+    match command {
+        DbCommand::Scan { full, paths } => {
+            let results = database
+                .scan(paths, &cli.process, cli.algorithm, *full)
+                .unwrap_or_else(|e| fail(&e));
+            for (file, event) in results {
+                match event {
+                    Ok(event) => println!("{}: {event}", file.display()),
+                    Err(e) => eprintln!("ERROR: {}: {e}", file.display()),
+                }
+            }
         }
+        DbCommand::Status { paths } => {
+            for (file, event) in database.status(paths).unwrap_or_else(|e| fail(&e)) {
+                println!("{}: {event}", file.display());
+            }
+        }
+        DbCommand::Export { json, paths } => {
+            for record in database.records().iter().filter(|record| {
+                paths.is_empty() || paths.iter().any(|x| record.path.starts_with(x))
+            }) {
+                if *json {
+                    println!("{}", record.json());
+                } else {
+                    println!("{record}");
+                }
+            }
+        }
+    }
+}
+
+/// Verify the files listed in checksum lists
+fn check_lists(cli: &Cli, options: &Options) -> Vec<anyhow::Result<String>> {
+    #[cfg(not(feature = "minisign"))]
+    let _ = options;
+    #[cfg(feature = "openpgp")]
+    let keys = cli
+        .keyring
+        .as_ref()
+        .map(load_keyring)
+        .transpose()
+        .unwrap_or_else(|e| fail(&e));
+    #[cfg(feature = "ssh")]
+    let allowed_signers = cli.allowed_signers.as_deref().map(allowed_signers);
+    cli.files
+        .iter()
+        .flat_map(|list| {
+            #[cfg(feature = "minisign")]
+            if let Some(key) = &options.trusted_key
+                && let Err(e) = verify_minisign(list, key)
+            {
+                return vec![Err(e)];
+            }
+            #[cfg(feature = "openpgp")]
+            if let Some(keys) = &keys {
+                #[cfg(feature = "ssh")]
+                if let Some(allowed_signers) = &allowed_signers
+                    && let Err(e) = fhc::verify_ssh(
+                        list,
+                        allowed_signers,
+                        cli.identity.as_deref(),
+                        &cli.namespace,
+                    )
+                {
+                    return vec![Err(e)];
+                }
+                return check_list_openpgp(list, keys).unwrap_or_else(|e| vec![Err(e)]);
+            }
+            #[cfg(feature = "ssh")]
+            if let Some(allowed_signers) = &allowed_signers {
+                return check_list_ssh(
+                    list,
+                    allowed_signers,
+                    cli.identity.as_deref(),
+                    &cli.namespace,
+                )
+                .unwrap_or_else(|e| vec![Err(e)]);
+            }
+            check_list(list).unwrap_or_else(|e| vec![Err(e)])
+        })
+        .collect()
+}
+
+/// Run in the dedicated thread pool, if any
+fn install<T: Send>(pool: Option<&ThreadPool>, f: impl FnOnce() -> T + Send) -> T {
+    match pool {
+        Some(pool) => pool.install(f),
+        None => f(),
     }
 }
+
+/// Parse a size in bytes with an optional `K`, `M`, `G`, or `T` (binary) suffix
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (number, shift) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 30),
+        Some((i, 'T' | 't')) => (&s[..i], 40),
+        _ => (s, 0),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .filter(|n| *n > 0)
+        .ok_or_else(|| format!("invalid size {s:?}"))
+}
+
+/// Load a minisign secret key, prompting for its password if encrypted
+#[cfg(feature = "minisign")]
+fn secret_key(path: &Path) -> SecretKey {
+    SecretKey::from_file(path, None)
+        .unwrap_or_else(|e| fail(&anyhow::anyhow!("{}: {e}", path.display())))
+}
+
+/// Load a minisign public key
+#[cfg(feature = "minisign")]
+fn public_key(path: &Path) -> PublicKey {
+    PublicKey::from_file(path).unwrap_or_else(|e| fail(&anyhow::anyhow!("{}: {e}", path.display())))
+}
+
+/// Load an SSH private key, prompting for its passphrase if encrypted
+#[cfg(feature = "ssh")]
+fn ssh_private_key(path: &Path) -> PrivateKey {
+    let fail_key =
+        |e: &dyn std::fmt::Display| -> ! { fail(&anyhow::anyhow!("{}: {e}", path.display())) };
+    let key = PrivateKey::read_openssh_file(path).unwrap_or_else(|e| fail_key(&e));
+    if !key.is_encrypted() {
+        return key;
+    }
+    let passphrase =
+        rpassword::prompt_password(format!("Enter passphrase for {}: ", path.display()))
+            .unwrap_or_else(|e| fail_key(&e));
+    key.decrypt(passphrase).unwrap_or_else(|e| fail_key(&e))
+}
+
+/// Load an `allowed_signers` file
+#[cfg(feature = "ssh")]
+fn allowed_signers(path: &Path) -> Vec<AllowedSigner> {
+    std::fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| parse_allowed_signers(&content))
+        .unwrap_or_else(|e| fail(&anyhow::anyhow!("{}: {e}", path.display())))
+}
+
+/// Print an error and exit
+fn fail(e: &anyhow::Error) -> ! {
+    eprintln!("ERROR: {e}");
+    std::process::exit(1);
+}
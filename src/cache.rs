@@ -0,0 +1,138 @@
+use {
+    crate::{Algorithm, Hash},
+    anyhow::Result,
+    std::{
+        fs::{Metadata, metadata},
+        os::unix::fs::MetadataExt,
+        path::Path,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// Prefix of the extended attributes holding cached digests (e.g. `user.fhc.b3`)
+const ATTRIBUTE_PREFIX: &str = "user.fhc.";
+
+/**
+Writing the attribute itself updates the ctime, so a cached digest stays valid for ctimes up to
+this long (in nanoseconds) after it was written
+*/
+const CTIME_SLACK: i128 = 1_000_000_000;
+
+/// Metadata that must be unchanged for a cached digest to be trusted
+#[derive(PartialEq, Eq)]
+struct Stamp {
+    size: u64,
+    mtime: i128,
+    ctime: i128,
+    inode: u64,
+}
+
+impl Stamp {
+    fn new(metadata: &Metadata) -> Stamp {
+        Stamp {
+            size: metadata.size(),
+            mtime: nanoseconds(metadata.mtime(), metadata.mtime_nsec()),
+            ctime: nanoseconds(metadata.ctime(), metadata.ctime_nsec()),
+            inode: metadata.ino(),
+        }
+    }
+}
+
+/**
+Get a file's digests cached in its `user.fhc.*` extended attributes (see [`Options::cache`])
+
+A digest is only returned if the file's size, mtime, and inode are the same as when it was cached
+and its ctime is not later than just after the digest was written, so content changes are noticed
+even if the mtime is reset.
+Returns `None` if any of the algorithms of `hash` has no valid cached digest.
+
+[`Options::cache`]: crate::Options::cache
+*/
+#[must_use]
+pub fn cached_hashes<P: AsRef<Path>>(file: P, hash: Hash) -> Option<Vec<(String, String)>> {
+    let file = file.as_ref();
+    let stamp = Stamp::new(&metadata(file).ok()?);
+    hash.algorithms()
+        .into_iter()
+        .map(|algorithm| {
+            let value = xattr::get(file, attribute(algorithm)).ok()??;
+            let value = String::from_utf8(value).ok()?;
+            let mut fields = value.split(' ');
+            let mut field = || fields.next();
+            let (size, mtime, ctime, inode, digest) = (
+                field()?.parse::<u64>().ok()?,
+                field()?.parse::<i128>().ok()?,
+                field()?.parse::<i128>().ok()?,
+                field()?.parse::<u64>().ok()?,
+                field()?,
+            );
+            (size == stamp.size
+                && mtime == stamp.mtime
+                && inode == stamp.inode
+                && stamp.ctime <= ctime
+                && digest
+                    .strip_prefix(algorithm.label())
+                    .is_some_and(|x| x.starts_with(':')))
+            .then(|| {
+                (
+                    format!("{}.{}", file.display(), algorithm.extension()),
+                    digest.to_string(),
+                )
+            })
+        })
+        .collect()
+}
+
+/**
+Get a file's digests from the cache unless `paranoid`, otherwise calculate them with `hash_file`
+and cache them
+
+Caching is best effort: files whose attributes cannot be written (e.g. read-only files or file
+systems without user extended attributes) or that change while being hashed are hashed every time.
+
+# Errors
+
+Returns an error if `hash_file` fails
+*/
+pub(crate) fn hashes<F: FnOnce() -> Result<Vec<(String, String)>>>(
+    file: &Path,
+    hash: Hash,
+    paranoid: bool,
+    hash_file: F,
+) -> Result<Vec<(String, String)>> {
+    if !paranoid && let Some(hashes) = cached_hashes(file, hash) {
+        return Ok(hashes);
+    }
+    let before = metadata(file).map(|m| Stamp::new(&m));
+    let hashes = hash_file()?;
+    if let Ok(before) = before
+        && metadata(file).is_ok_and(|m| Stamp::new(&m) == before)
+    {
+        for ((_, digest), algorithm) in hashes.iter().zip(hash.algorithms()) {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos().try_into().unwrap_or(i128::MAX));
+            let value = format!(
+                "{} {} {} {} {digest}",
+                before.size,
+                before.mtime,
+                now.saturating_add(CTIME_SLACK),
+                before.inode
+            );
+            if xattr::set(file, attribute(algorithm), value.as_bytes()).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(hashes)
+}
+
+/// Name of the extended attribute caching the digest of an algorithm
+fn attribute(algorithm: Algorithm) -> String {
+    format!("{ATTRIBUTE_PREFIX}{}", algorithm.extension())
+}
+
+/// Combine seconds and nanoseconds
+fn nanoseconds(seconds: i64, nanoseconds: i64) -> i128 {
+    i128::from(seconds) * 1_000_000_000 + i128::from(nanoseconds)
+}
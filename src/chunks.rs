@@ -0,0 +1,239 @@
+use {
+    crate::{Entry, Hash, Hasher},
+    anyhow::{Result, bail},
+    std::{
+        fmt::Write as _,
+        fs::File,
+        io::{Read, Write, sink},
+        ops::Range,
+        path::Path,
+    },
+};
+
+/// Size of the read buffer for chunked hashing
+const CHUNK_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Per-chunk digests of a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunks {
+    /// Size of each chunk in bytes (the last chunk may be shorter)
+    pub chunk_size: u64,
+
+    /// Size of the file in bytes
+    pub size: u64,
+
+    /// Lowercase hex digest of each chunk
+    pub digests: Vec<String>,
+}
+
+impl Chunks {
+    /**
+    Format as hash file comment lines
+
+    ```text
+    # chunks <chunk size> <file size>
+    # chunk <index> <digest>
+    ```
+    */
+    #[must_use]
+    pub fn lines(&self) -> String {
+        let mut r = format!("# chunks {} {}\n", self.chunk_size, self.size);
+        for (i, digest) in self.digests.iter().enumerate() {
+            let _ = writeln!(r, "# chunk {i} {digest}");
+        }
+        r
+    }
+
+    /**
+    Get the byte ranges that differ from another file's chunks with the same chunk size
+
+    Adjacent corrupted chunks are merged and chunks missing from either side (the file grew or
+    shrank) are corrupted too.
+    */
+    #[must_use]
+    pub fn corrupted(&self, actual: &Chunks) -> Vec<Range<u64>> {
+        let size = self.size.max(actual.size);
+        let mut r: Vec<Range<u64>> = vec![];
+        for i in 0..self.digests.len().max(actual.digests.len()) {
+            if self.digests.get(i).is_some() && self.digests.get(i) == actual.digests.get(i) {
+                continue;
+            }
+            let start = i as u64 * self.chunk_size;
+            let end = (start + self.chunk_size).min(size);
+            match r.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => r.push(start..end),
+            }
+        }
+        r
+    }
+}
+
+/**
+Hash a file and its chunks in a single pass
+
+Returns the hash(es) as `(ckfile, hash)` tuples (see [`Hash::hash_file`]) and the chunk digests
+for each algorithm of `hash` in the same order.
+
+# Errors
+
+Returns an error if the chunk size is zero or if not able to read the given file
+*/
+#[allow(clippy::type_complexity)]
+pub fn file_chunks<P: AsRef<Path>>(
+    file: P,
+    hash: Hash,
+    chunk_size: u64,
+) -> Result<(Vec<(String, String)>, Vec<Chunks>)> {
+    let (hashes, chunks) = hash_into(file.as_ref(), hash, Some(chunk_size), &mut sink())?;
+    Ok((hashes, chunks.unwrap_or_default()))
+}
+
+/**
+Hash a file and its chunks (if `chunk_size` is given) while copying its content to `out`
+
+# Errors
+
+Returns an error if the chunk size is zero, if not able to read the given file, or if not able
+to write to `out`
+*/
+#[allow(clippy::type_complexity)]
+pub(crate) fn hash_into<W: Write>(
+    file: &Path,
+    hash: Hash,
+    chunk_size: Option<u64>,
+    out: &mut W,
+) -> Result<(Vec<(String, String)>, Option<Vec<Chunks>>)> {
+    if chunk_size == Some(0) {
+        bail!("chunk size must be greater than zero");
+    }
+    let mut f = File::open(file)?;
+    let mut hasher = Hasher::new(hash);
+    let mut chunk = Hasher::new(hash);
+    let mut chunk_len = 0;
+    let mut chunks = chunk_size.map(|chunk_size| {
+        hash.algorithms()
+            .into_iter()
+            .map(|_| Chunks {
+                chunk_size,
+                size: 0,
+                digests: vec![],
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let mut finish = |chunk: Hasher| -> Result<()> {
+        let Some(chunks) = &mut chunks else {
+            return Ok(());
+        };
+        for (chunks, (_, hash)) in chunks.iter_mut().zip(chunk.finalize(file)?) {
+            let digest = hash.split_once(':').map_or(hash.as_str(), |x| x.1);
+            chunks.digests.push(digest.to_string());
+        }
+        Ok(())
+    };
+
+    let mut buffer = vec![0; CHUNK_BUFFER_SIZE];
+    let mut size = 0;
+    loop {
+        let bytes_read = f.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        let mut buf = &buffer[..bytes_read];
+        hasher.update(buf);
+        out.write_all(buf)?;
+        size += bytes_read as u64;
+        let Some(chunk_size) = chunk_size else {
+            continue;
+        };
+        while !buf.is_empty() {
+            let n = buf
+                .len()
+                .min(usize::try_from(chunk_size - chunk_len).unwrap_or(usize::MAX));
+            chunk.update(&buf[..n]);
+            chunk_len += n as u64;
+            buf = &buf[n..];
+            if chunk_len == chunk_size {
+                finish(std::mem::replace(&mut chunk, Hasher::new(hash)))?;
+                chunk_len = 0;
+            }
+        }
+    }
+    if chunk_len > 0 {
+        finish(chunk)?;
+    }
+
+    for chunks in chunks.iter_mut().flatten() {
+        chunks.size = size;
+    }
+    Ok((hasher.finalize(file)?, chunks))
+}
+
+/**
+Describe the corrupted byte ranges of a file that failed verification against an entry
+
+Returns an empty string if the entry has no chunk digests, otherwise e.g.
+` (corrupted bytes 0-1048575, 4194304-4194400)` with inclusive ranges.
+
+# Errors
+
+Returns an error if not able to read the given file
+*/
+pub(crate) fn corruption(file: &Path, entry: &Entry) -> Result<String> {
+    let Some(expected) = &entry.chunks else {
+        return Ok(String::new());
+    };
+    let (_, actual) = file_chunks(file, Hash::from(entry.algorithm), expected.chunk_size)?;
+    let ranges = expected
+        .corrupted(&actual[0])
+        .iter()
+        .map(|range| format!("{}-{}", range.start, range.end.saturating_sub(1)))
+        .collect::<Vec<_>>();
+    Ok(if ranges.is_empty() {
+        String::new()
+    } else {
+        format!(" (corrupted bytes {})", ranges.join(", "))
+    })
+}
+
+/**
+Apply a `# chunks` or `# chunk` comment line to the last entry of a hash file
+
+Other comments, including ones that merely start with these words, are ignored.
+*/
+pub(crate) fn parse_chunk_comment(line: &str, entries: &mut [Entry]) -> Result<()> {
+    let Some(entry) = entries.last_mut() else {
+        return Ok(());
+    };
+    let mut words = line.trim_start_matches('#').split_whitespace();
+    match (words.next(), words.next(), words.next(), words.next()) {
+        (Some("chunks"), Some(chunk_size), Some(size), None) => {
+            if let (Ok(chunk_size @ 1..), Ok(size)) = (chunk_size.parse(), size.parse()) {
+                entry.chunks = Some(Chunks {
+                    chunk_size,
+                    size,
+                    digests: vec![],
+                });
+            }
+        }
+        (Some("chunk"), Some(index), Some(digest), None) => {
+            let algorithm = entry.algorithm;
+            let Some(chunks) = &mut entry.chunks else {
+                return Ok(());
+            };
+            if index.parse() != Ok(chunks.digests.len()) {
+                bail!("chunk {index} out of order");
+            }
+            let digest = digest.to_ascii_lowercase();
+            if digest.len() != algorithm.digest_len() * 2
+                || !digest.bytes().all(|b| b.is_ascii_hexdigit())
+            {
+                bail!("invalid {} chunk digest {digest:?}", algorithm.label());
+            }
+            chunks.digests.push(digest);
+        }
+        _ => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,481 @@
+use {
+    crate::{
+        Hash, ProcessOption,
+        diff::{expand, hash_files},
+        json::json_string,
+        stat,
+    },
+    anyhow::{Result, anyhow, bail},
+    std::{
+        collections::BTreeMap,
+        fs::{OpenOptions, read_to_string},
+        io::Write,
+        path::{Path, PathBuf},
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// First line of a database file
+const HEADER: &str = "# fhc database v1";
+
+/// Change of a file recorded by a scan
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Not in the database before
+    New,
+
+    /// Size or modification time changed
+    Modified,
+
+    /// Metadata unchanged and hashes match
+    Ok,
+
+    /// Metadata unchanged but hashes do not match (possible corruption)
+    Failed,
+
+    /// No longer present
+    Deleted,
+}
+
+impl Event {
+    /// Get an event from its name
+    fn from_name(name: &str) -> Option<Event> {
+        [
+            Event::New,
+            Event::Modified,
+            Event::Ok,
+            Event::Failed,
+            Event::Deleted,
+        ]
+        .into_iter()
+        .find(|event| event.to_string() == name)
+    }
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Event::New => "NEW",
+            Event::Modified => "MODIFIED",
+            Event::Ok => "OK",
+            Event::Failed => "FAILED",
+            Event::Deleted => "DELETED",
+        })
+    }
+}
+
+/// Record of a file in a [`Database`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// Number of the scan (starting at 1; scans without changes are not recorded)
+    pub scan: u64,
+
+    /// Unix time of the scan
+    pub time: u64,
+
+    /// Event
+    pub event: Event,
+
+    /// Size in bytes (`None` if deleted)
+    pub size: Option<u64>,
+
+    /// Modification time in nanoseconds since the Unix epoch (`None` if deleted)
+    pub mtime: Option<i128>,
+
+    /// `LABEL:digest` hashes (empty if deleted)
+    pub hashes: Vec<String>,
+
+    /// Path
+    pub path: PathBuf,
+}
+
+impl Record {
+    /// Encode as a line of a database file
+    fn line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            self.scan,
+            self.time,
+            self.event,
+            self.size
+                .map_or_else(|| String::from("-"), |x| x.to_string()),
+            self.mtime
+                .map_or_else(|| String::from("-"), |x| x.to_string()),
+            if self.hashes.is_empty() {
+                String::from("-")
+            } else {
+                self.hashes.join(" ")
+            },
+            escape(&self.path.display().to_string()),
+        )
+    }
+
+    /// Decode a line of a database file
+    fn parse(line: &str) -> Option<Record> {
+        let mut fields = line.splitn(7, '\t');
+        let mut field = || fields.next();
+        Some(Record {
+            scan: field()?.parse().ok()?,
+            time: field()?.parse().ok()?,
+            event: Event::from_name(field()?)?,
+            size: optional(field()?).map(str::parse).transpose().ok()?,
+            mtime: optional(field()?).map(str::parse).transpose().ok()?,
+            hashes: optional(field()?)
+                .map(|x| x.split(' ').map(ToString::to_string).collect())
+                .unwrap_or_default(),
+            path: PathBuf::from(unescape(field()?)?),
+        })
+    }
+
+    /// Encode as a JSON object
+    #[must_use]
+    pub fn json(&self) -> String {
+        let number = |x: Option<String>| x.unwrap_or_else(|| String::from("null"));
+        format!(
+            "{{\"scan\":{},\"time\":{},\"event\":{},\"path\":{},\"size\":{},\"mtime\":{},\"hashes\":[{}]}}",
+            self.scan,
+            self.time,
+            json_string(&self.event.to_string()),
+            json_string(&self.path.display().to_string()),
+            number(self.size.map(|x| x.to_string())),
+            number(self.mtime.map(|x| x.to_string())),
+            self.hashes
+                .iter()
+                .map(|x| json_string(x))
+                .collect::<Vec<_>>()
+                .join(","),
+        )
+    }
+}
+
+impl std::fmt::Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}: {}",
+            self.scan,
+            self.time,
+            self.path.display(),
+            self.event
+        )
+    }
+}
+
+/**
+Persistent hash database for incremental scans
+
+The database is an append-only log (one tab-separated [`Record`] per line) of every file's
+hashes, size, and modification time, as recorded by each [`Database::scan`], so it doubles as
+the files' verification history.
+Paths are recorded as given (joined with the relative paths below directories), so a tree should
+always be scanned via the same path.
+*/
+#[derive(Clone, Debug)]
+pub struct Database {
+    path: PathBuf,
+    records: Vec<Record>,
+}
+
+impl Database {
+    /**
+    Open a database file (empty if it does not exist yet)
+
+    # Errors
+
+    Returns an error if not able to read the file or if it is malformed
+    */
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Database> {
+        let path = path.as_ref();
+        let mut records = vec![];
+        if path.exists() {
+            let content = read_to_string(path)?;
+            let mut lines = content.lines().enumerate();
+            if lines.next().is_none_or(|(_, line)| line != HEADER) {
+                bail!("{}: not an fhc database", path.display());
+            }
+            for (i, line) in lines {
+                records.push(
+                    Record::parse(line)
+                        .ok_or_else(|| anyhow!("{}: malformed line {}", path.display(), i + 1))?,
+                );
+            }
+        }
+        Ok(Database {
+            path: path.to_path_buf(),
+            records,
+        })
+    }
+
+    /// Get all records in the order they were recorded
+    #[must_use]
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Get the number of the last scan (0 if none)
+    #[must_use]
+    pub fn last_scan(&self) -> u64 {
+        self.records.last().map_or(0, |record| record.scan)
+    }
+
+    /// Get the latest record of every file that is not deleted, by path
+    #[must_use]
+    pub fn files(&self) -> BTreeMap<&Path, &Record> {
+        let mut r = BTreeMap::new();
+        for record in &self.records {
+            if record.event == Event::Deleted {
+                r.remove(record.path.as_path());
+            } else {
+                r.insert(record.path.as_path(), record);
+            }
+        }
+        r
+    }
+
+    /**
+    List the files below the given paths that are new, modified, or deleted since the last scan
+
+    Only metadata (size and modification time) is compared, so nothing is hashed or recorded.
+    Results are `(path, event)` tuples sorted by path.
+
+    # Errors
+
+    Returns an error if not able to read a directory or the metadata of a file
+    */
+    pub fn status<P: AsRef<Path>>(&self, paths: &[P]) -> Result<Vec<(PathBuf, Event)>> {
+        let known = self.files();
+        let mut r = vec![];
+        for file in self.expand(paths)? {
+            let (size, mtime) = stat(&file)?;
+            match known.get(file.as_path()) {
+                None => r.push((file, Event::New)),
+                Some(record) if record.size != Some(size) || record.mtime != Some(mtime) => {
+                    r.push((file, Event::Modified));
+                }
+                Some(_) => {}
+            }
+        }
+        r.extend(
+            deleted(&known, paths)
+                .into_iter()
+                .map(|file| (file, Event::Deleted)),
+        );
+        r.sort_by(|x, y| x.0.cmp(&y.0));
+        Ok(r)
+    }
+
+    /**
+    Scan the files below the given paths and record them as a new scan
+
+    New files and files whose size or modification time changed are hashed with the given process
+    option and algorithm(s) and recorded as [`Event::New`] or [`Event::Modified`], and files that
+    disappeared are recorded as [`Event::Deleted`].
+    Unchanged files are skipped, unless `full`, which re-hashes them and records [`Event::Ok`] if
+    the hashes of the algorithms in common with their last good record (any but
+    [`Event::Failed`]) match, or [`Event::Failed`] otherwise, so a failed file keeps failing
+    until it is modified.
+    The database file itself is never scanned.
+    Results are the recorded `(path, event)` tuples sorted by path, with errors hashing a file
+    returned as that file's event (and nothing recorded for it).
+
+    # Errors
+
+    Returns an error if not able to read a directory or the metadata of a file, or to write the
+    database
+    */
+    pub fn scan<P: AsRef<Path>>(
+        &mut self,
+        paths: &[P],
+        process: &ProcessOption,
+        hash: Hash,
+        full: bool,
+    ) -> Result<Vec<(PathBuf, Result<Event>)>> {
+        let known = self.files();
+        let good = self.good_hashes();
+        let mut pending = vec![];
+        for file in self.expand(paths)? {
+            let (size, mtime) = stat(&file)?;
+            let event = match known.get(file.as_path()) {
+                None => Event::New,
+                Some(record) if record.size != Some(size) || record.mtime != Some(mtime) => {
+                    Event::Modified
+                }
+                Some(_) if full => Event::Ok,
+                Some(_) => continue,
+            };
+            pending.push((file, event, size, mtime));
+        }
+        let gone = deleted(&known, paths);
+
+        // Hash the pending files in a single run
+        let files = pending
+            .iter()
+            .map(|(file, ..)| file.clone())
+            .collect::<Vec<_>>();
+        let hashes = hash_files(&files, process, hash);
+
+        let scan = self.last_scan() + 1;
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut records = vec![];
+        let mut r = vec![];
+        for ((file, event, size, mtime), hashes) in pending.into_iter().zip(hashes) {
+            let hashes = match hashes {
+                Ok(hashes) => hashes,
+                Err(e) => {
+                    r.push((file, Err(e)));
+                    continue;
+                }
+            };
+            let event = match (event, good.get(file.as_path())) {
+                (Event::Ok, Some(good)) if !matches(good, &hashes) => Event::Failed,
+                (event, _) => event,
+            };
+            records.push(Record {
+                scan,
+                time,
+                event,
+                size: Some(size),
+                mtime: Some(mtime),
+                hashes,
+                path: file.clone(),
+            });
+            r.push((file, Ok(event)));
+        }
+        for file in gone {
+            records.push(Record {
+                scan,
+                time,
+                event: Event::Deleted,
+                size: None,
+                mtime: None,
+                hashes: vec![],
+                path: file.clone(),
+            });
+            r.push((file, Ok(Event::Deleted)));
+        }
+
+        self.append(records)?;
+        r.sort_by(|x, y| x.0.cmp(&y.0));
+        Ok(r)
+    }
+
+    /// Get the hashes of the latest record other than [`Event::Failed`] of every file that is not
+    /// deleted, by path
+    fn good_hashes(&self) -> BTreeMap<&Path, &[String]> {
+        let mut r = BTreeMap::new();
+        for record in &self.records {
+            match record.event {
+                Event::Deleted => {
+                    r.remove(record.path.as_path());
+                }
+                Event::Failed => {}
+                _ => {
+                    r.insert(record.path.as_path(), record.hashes.as_slice());
+                }
+            }
+        }
+        r
+    }
+
+    /**
+    List the files below the given paths, except the database file itself
+
+    # Errors
+
+    Returns an error if not able to read a directory
+    */
+    fn expand<P: AsRef<Path>>(&self, paths: &[P]) -> Result<Vec<PathBuf>> {
+        let database = canonical(&self.path);
+        Ok(expand(paths)?
+            .into_iter()
+            .filter(|file| {
+                file.file_name() != self.path.file_name()
+                    || database.is_none()
+                    || canonical(file) != database
+            })
+            .collect())
+    }
+
+    /// Append records to the database file
+    fn append(&mut self, records: Vec<Record>) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut content = String::new();
+        if !self.path.exists() {
+            content.push_str(HEADER);
+            content.push('\n');
+        }
+        for record in &records {
+            content.push_str(&record.line());
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut f| f.write_all(content.as_bytes()))
+            .map_err(|e| anyhow!("{}: {e}", self.path.display()))?;
+        self.records.extend(records);
+        Ok(())
+    }
+}
+
+/// Get the canonical path of a file that may not exist yet (via its parent directory)
+fn canonical(file: &Path) -> Option<PathBuf> {
+    let parent = file
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    Some(parent.canonicalize().ok()?.join(file.file_name()?))
+}
+
+/// List the known files below the given paths that no longer exist
+fn deleted<P: AsRef<Path>>(known: &BTreeMap<&Path, &Record>, paths: &[P]) -> Vec<PathBuf> {
+    known
+        .keys()
+        .filter(|file| paths.iter().any(|path| file.starts_with(path)) && !file.exists())
+        .map(|file| file.to_path_buf())
+        .collect()
+}
+
+/// Check whether the hashes of the algorithms in common match
+fn matches(previous: &[String], current: &[String]) -> bool {
+    previous.iter().all(|hash| {
+        let label = hash.split(':').next();
+        current
+            .iter()
+            .all(|x| x.split(':').next() != label || x == hash)
+    })
+}
+
+/// Get a field of a database line, `None` if `-`
+fn optional(field: &str) -> Option<&str> {
+    (field != "-").then_some(field)
+}
+
+/// Escape backslashes, tabs, and newlines in a path
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+/// Reverse [`escape`]
+fn unescape(s: &str) -> Option<String> {
+    let mut r = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        r.push(match c {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                't' => '\t',
+                'n' => '\n',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(r)
+}
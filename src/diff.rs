@@ -0,0 +1,267 @@
+use {
+    crate::{
+        Algorithm, Hash, HashOnly, Options, ProcessOption,
+        manifest::{is_hashdeep, parse_hashdeep},
+        mtree::is_mtree,
+        parse_mtree, parse_sidecar,
+    },
+    anyhow::{Result, anyhow, bail},
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        fs::{read_dir, read_to_string, symlink_metadata},
+        path::{Component, Path, PathBuf},
+    },
+};
+
+/// Result of comparing a file in two trees
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difference {
+    /// Same content in both trees
+    Identical,
+
+    /// Different content
+    Different,
+
+    /// Only in the first tree
+    OnlyInA,
+
+    /// Only in the second tree (or manifest)
+    OnlyInB,
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Difference::Identical => "IDENTICAL",
+                Difference::Different => "DIFFERENT",
+                Difference::OnlyInA => "ONLY IN A",
+                Difference::OnlyInB => "ONLY IN B",
+            }
+        )
+    }
+}
+
+/**
+Compare two directory trees by content
+
+Both trees are walked recursively (regular files only; symbolic links are not followed) and the
+files present in both are hashed with the given process option, so files only in one tree are
+never read.
+Results are `(relative path, difference)` tuples sorted by path.
+
+# Errors
+
+Returns an error if not able to read either directory; errors hashing a file are returned as
+that file's difference
+*/
+pub fn diff<A: AsRef<Path>, B: AsRef<Path>>(
+    a: A,
+    b: B,
+    process: &ProcessOption,
+    hash: Hash,
+) -> Result<Vec<(PathBuf, Result<Difference>)>> {
+    let (a, b) = (a.as_ref(), b.as_ref());
+    let files_a = walk(a)?;
+    let files_b = walk(b)?;
+    let common = files_a.intersection(&files_b).cloned().collect::<Vec<_>>();
+
+    // Hash the common files of both trees in a single run
+    let files = common
+        .iter()
+        .map(|file| a.join(file))
+        .chain(common.iter().map(|file| b.join(file)))
+        .collect::<Vec<_>>();
+    let mut hashes = hash_files(&files, process, hash).into_iter();
+    let hashes_a = common
+        .iter()
+        .cloned()
+        .zip(hashes.by_ref())
+        .collect::<BTreeMap<_, _>>();
+    let hashes_b = common
+        .iter()
+        .cloned()
+        .zip(hashes)
+        .collect::<BTreeMap<_, _>>();
+
+    Ok(compare(&files_a, &files_b, hashes_a, &hashes_b))
+}
+
+/**
+Compare a directory tree to a previously saved manifest
+
+The manifest may be a hashdeep manifest, an mtree spec, or a checksum list (see
+[`check_list`](crate::check_list)); its paths are taken relative to the tree's root (a leading
+`./` is ignored).
+The files present in both are hashed with every algorithm the manifest uses and are identical if
+all of the manifest's hashes for the file match.
+Results are `(relative path, difference)` tuples sorted by path, with files only in the manifest
+reported as [`Difference::OnlyInB`].
+
+# Errors
+
+Returns an error if not able to read the directory or the manifest, if the manifest is malformed,
+or if it has no BLAKE3, SHA256, or SHA512 hashes
+*/
+pub fn diff_manifest<A: AsRef<Path>, M: AsRef<Path>>(
+    a: A,
+    manifest: M,
+    process: &ProcessOption,
+) -> Result<Vec<(PathBuf, Result<Difference>)>> {
+    let (a, manifest) = (a.as_ref(), manifest.as_ref());
+    let expected = load_manifest(manifest).map_err(|e| anyhow!("{}: {e}", manifest.display()))?;
+    let algorithms = expected
+        .values()
+        .flatten()
+        .filter_map(|hash| Algorithm::from_label(hash.split(':').next()?))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let hash = Hash::from_algorithms(&algorithms)
+        .ok_or_else(|| anyhow!("{}: no hashes found", manifest.display()))?;
+
+    let files_a = walk(a)?;
+    let files_b = expected.keys().cloned().collect::<BTreeSet<_>>();
+    let common = files_a.intersection(&files_b).cloned().collect::<Vec<_>>();
+    let files = common.iter().map(|file| a.join(file)).collect::<Vec<_>>();
+    let hashes_a = common
+        .iter()
+        .cloned()
+        .zip(hash_files(&files, process, hash))
+        .collect::<BTreeMap<_, _>>();
+    let hashes_b = expected
+        .into_iter()
+        .map(|(file, hashes)| (file, Ok(hashes)))
+        .collect::<BTreeMap<_, _>>();
+
+    Ok(compare(&files_a, &files_b, hashes_a, &hashes_b))
+}
+
+/// Classify every file of two trees given the hashes of the common files
+fn compare(
+    files_a: &BTreeSet<PathBuf>,
+    files_b: &BTreeSet<PathBuf>,
+    hashes_a: BTreeMap<PathBuf, Result<Vec<String>>>,
+    hashes_b: &BTreeMap<PathBuf, Result<Vec<String>>>,
+) -> Vec<(PathBuf, Result<Difference>)> {
+    let mut r = files_a
+        .difference(files_b)
+        .map(|file| (file.clone(), Ok(Difference::OnlyInA)))
+        .chain(
+            files_b
+                .difference(files_a)
+                .map(|file| (file.clone(), Ok(Difference::OnlyInB))),
+        )
+        .collect::<Vec<_>>();
+
+    for (file, a) in hashes_a {
+        let difference = match (a, &hashes_b[&file]) {
+            (Err(e), _) => Err(e),
+            (_, Err(e)) => Err(anyhow!("{e}")),
+            (Ok(a), Ok(b)) => Ok(if b.iter().all(|hash| a.contains(hash)) {
+                Difference::Identical
+            } else {
+                Difference::Different
+            }),
+        };
+        r.push((file, difference));
+    }
+
+    r.sort_by(|x, y| x.0.cmp(&y.0));
+    r
+}
+
+/// Hash files without accessing hash files and return the `LABEL:digest` hashes of each file
+pub(crate) fn hash_files(
+    files: &[PathBuf],
+    process: &ProcessOption,
+    hash: Hash,
+) -> Vec<Result<Vec<String>>> {
+    process
+        .run_by(files, &HashOnly(Options::from(hash)))
+        .into_iter()
+        .map(|result| Ok(result?.into_iter().map(|(_, hash)| hash).collect()))
+        .collect()
+}
+
+/// List the regular files in a directory tree as paths relative to its root
+pub(crate) fn walk(root: &Path) -> Result<BTreeSet<PathBuf>> {
+    let mut r = BTreeSet::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        let entries =
+            read_dir(root.join(&dir)).map_err(|e| anyhow!("{}: {e}", root.join(&dir).display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = dir.join(entry.file_name());
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_file() {
+                r.insert(path);
+            }
+        }
+    }
+    Ok(r)
+}
+
+/// List the given files and the regular files in the given directory trees, sorted
+pub(crate) fn expand<P: AsRef<Path>>(paths: &[P]) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        let path = path.as_ref();
+        if symlink_metadata(path)
+            .map_err(|e| anyhow!("{}: {e}", path.display()))?
+            .is_dir()
+        {
+            files.extend(walk(path)?.into_iter().map(|file| path.join(file)));
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Read a manifest into `LABEL:digest` hashes by relative path
+fn load_manifest(manifest: &Path) -> Result<BTreeMap<PathBuf, Vec<String>>> {
+    let content = read_to_string(manifest)?;
+    let mut r: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+
+    if is_hashdeep(&content) {
+        for entry in parse_hashdeep(&content)?.1 {
+            r.entry(relative(&entry.filename))
+                .or_default()
+                .extend(entry.hashes);
+        }
+    } else if is_mtree(&content) {
+        for entry in parse_mtree(&content)? {
+            if entry.kind.as_deref().is_none_or(|kind| kind == "file") && !entry.digests.is_empty()
+            {
+                r.entry(relative(&entry.path))
+                    .or_default()
+                    .extend(entry.digests);
+            }
+        }
+    } else {
+        for entry in parse_sidecar(&content, Algorithm::from_path(manifest))? {
+            let Some(filename) = &entry.filename else {
+                bail!("malformed sidecar at line {}: missing filename", entry.line);
+            };
+            r.entry(relative(filename)).or_default().push(entry.hash());
+        }
+    }
+
+    Ok(r)
+}
+
+/// Convert a manifest path to a path relative to the tree's root
+fn relative(path: &str) -> PathBuf {
+    Path::new(path)
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
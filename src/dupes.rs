@@ -0,0 +1,327 @@
+use {
+    crate::{BUFFER_SIZE, diff::expand, file_blake3, json::json_string},
+    anyhow::{Result, anyhow, bail},
+    clap::ValueEnum,
+    rayon::prelude::*,
+    std::{
+        collections::BTreeMap,
+        fmt::Write as _,
+        fs::{File, hard_link, remove_file, rename},
+        io::{self, ErrorKind, Read},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Set of files with identical content
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateSet {
+    /// BLAKE3 hash (`BLAKE3:digest`)
+    pub hash: String,
+
+    /// Size of each file in bytes
+    pub size: u64,
+
+    /// Files, sorted
+    pub files: Vec<PathBuf>,
+}
+
+impl DuplicateSet {
+    /// Bytes used by all but one of the files
+    #[must_use]
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.files.len() as u64 - 1)
+    }
+}
+
+/// Ways to replace duplicates with the first file of their set
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Link {
+    /// Hard link (shares the inode, including permissions and ownership)
+    Hardlink,
+
+    /// Copy-on-write clone (Linux `FICLONE`, e.g. on Btrfs or XFS; keeps permissions)
+    Reflink,
+}
+
+/**
+Find files with identical content
+
+Directories are walked recursively (regular files only; symbolic links are not followed).
+Files are grouped by size, then by a BLAKE3 hash of their first 4 KiB, and finally by their full
+BLAKE3 hash (see [`file_blake3`]), so only files that share a size and a prefix are read
+completely.
+Empty files are skipped, as are additional paths to an already hard linked file on Unix.
+Sets are sorted by decreasing wasted bytes.
+
+# Errors
+
+Returns an error if not able to read a directory or any of the files
+*/
+pub fn find_duplicates<P: AsRef<Path>>(paths: &[P]) -> Result<Vec<DuplicateSet>> {
+    let files = expand(paths)?;
+
+    // Group by size
+    let sizes = files
+        .par_iter()
+        .map(|file| {
+            let metadata = file
+                .metadata()
+                .map_err(|e| anyhow!("{}: {e}", file.display()))?;
+            Ok((metadata.len(), inode(&metadata), file.clone()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut by_size: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+    let mut inodes = std::collections::HashSet::new();
+    for (size, inode, file) in sizes {
+        if size > 0 && inode.is_none_or(|inode| inodes.insert(inode)) {
+            by_size.entry(size).or_default().push(file);
+        }
+    }
+    let candidates = by_size
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .flat_map(|(size, files)| files.into_iter().map(move |file| (size, file)))
+        .collect::<Vec<_>>();
+
+    // Group by partial hash
+    let partial = group(&candidates, |_, file| partial_hash(file))?
+        .into_iter()
+        .map(|((size, _), file)| (size, file))
+        .collect::<Vec<_>>();
+
+    // Group by full hash (the partial hash is complete for small files)
+    let full = group(&partial, |size, file| {
+        if size <= BUFFER_SIZE as u64 {
+            partial_hash(file)
+        } else {
+            Ok(file_blake3(file)?.remove(0).1)
+        }
+    })?;
+
+    let mut r = BTreeMap::<(u64, String), Vec<PathBuf>>::new();
+    for ((size, hash), file) in full {
+        r.entry((size, hash)).or_default().push(file);
+    }
+    let mut r = r
+        .into_iter()
+        .map(|((size, hash), files)| DuplicateSet { hash, size, files })
+        .collect::<Vec<_>>();
+    r.sort_by(|a, b| {
+        b.wasted()
+            .cmp(&a.wasted())
+            .then_with(|| a.files.cmp(&b.files))
+    });
+    Ok(r)
+}
+
+/// Key each `(size, file)` pair and keep only the pairs whose size and key are shared
+fn group<K, F>(files: &[(u64, PathBuf)], key: F) -> Result<Vec<((u64, K), PathBuf)>>
+where
+    K: Clone + Ord + Send,
+    F: Fn(u64, &Path) -> Result<K> + Sync,
+{
+    let keyed = files
+        .par_iter()
+        .map(|(size, file)| {
+            let key = key(*size, file).map_err(|e| anyhow!("{}: {e}", file.display()))?;
+            Ok(((*size, key), file.clone()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut groups: BTreeMap<(u64, K), Vec<PathBuf>> = BTreeMap::new();
+    for (key, file) in keyed {
+        groups.entry(key).or_default().push(file);
+    }
+    Ok(groups
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .flat_map(|((size, key), files)| {
+            files
+                .into_iter()
+                .map(move |file| ((size, key.clone()), file))
+        })
+        .collect())
+}
+
+/// BLAKE3 hash of the first 4 KiB of a file
+fn partial_hash(file: &Path) -> Result<String> {
+    let mut buffer = Vec::with_capacity(BUFFER_SIZE);
+    File::open(file)?
+        .take(BUFFER_SIZE as u64)
+        .read_to_end(&mut buffer)?;
+    Ok(format!("BLAKE3:{}", blake3::hash(&buffer)))
+}
+
+/// Get the device and inode numbers from metadata
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn inode(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Get the device and inode numbers from metadata (unavailable)
+#[cfg(not(unix))]
+fn inode(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Summarize duplicate sets as text
+#[must_use]
+pub fn duplicates_text(sets: &[DuplicateSet]) -> String {
+    let mut r = String::new();
+    for set in sets {
+        let _ = writeln!(
+            r,
+            "{} ({} bytes x {}, {} wasted)",
+            set.hash,
+            set.size,
+            set.files.len(),
+            set.wasted()
+        );
+        for file in &set.files {
+            let _ = writeln!(r, "  {}", file.display());
+        }
+    }
+    let _ = writeln!(
+        r,
+        "{} duplicate sets, {} wasted bytes",
+        sets.len(),
+        sets.iter().map(DuplicateSet::wasted).sum::<u64>()
+    );
+    r
+}
+
+/// Summarize duplicate sets as JSON
+#[must_use]
+pub fn duplicates_json(sets: &[DuplicateSet]) -> String {
+    let mut r = String::from("{\"sets\":[");
+    for (i, set) in sets.iter().enumerate() {
+        if i > 0 {
+            r.push(',');
+        }
+        let files = set
+            .files
+            .iter()
+            .map(|file| json_string(&file.display().to_string()))
+            .collect::<Vec<_>>()
+            .join(",");
+        let _ = write!(
+            r,
+            "{{\"hash\":{},\"size\":{},\"wasted\":{},\"files\":[{files}]}}",
+            json_string(&set.hash),
+            set.size,
+            set.wasted()
+        );
+    }
+    let _ = write!(
+        r,
+        "],\"wasted\":{}}}",
+        sets.iter().map(DuplicateSet::wasted).sum::<u64>()
+    );
+    r
+}
+
+/**
+Replace every file of a duplicate set except the first with a link to the first
+
+Each file's content is re-checked against the set's hash right before it is replaced, and the
+link is created under a temporary name and renamed over the file, so a file is never lost or
+replaced by different content.
+Results are returned per replaced file.
+*/
+#[must_use]
+pub fn dedupe(set: &DuplicateSet, link: Link) -> Vec<Result<String>> {
+    let Some((keep, files)) = set.files.split_first() else {
+        return vec![];
+    };
+    files
+        .iter()
+        .map(|file| {
+            replace(keep, file, &set.hash, link).map_err(|e| anyhow!("{}: {e}", file.display()))
+        })
+        .collect()
+}
+
+/// Replace a file with a link to another after confirming both still have the expected hash
+fn replace(keep: &Path, file: &Path, hash: &str, link: Link) -> Result<String> {
+    for f in [keep, file] {
+        if file_blake3(f)?[0].1 != hash {
+            bail!("{} changed since it was hashed", f.display());
+        }
+    }
+
+    let temp = temp_link(keep, file, link)?;
+    if let Err(e) = rename(&temp, file) {
+        let _ = remove_file(&temp);
+        return Err(e.into());
+    }
+
+    Ok(format!(
+        "{}: {} to {}",
+        file.display(),
+        match link {
+            Link::Hardlink => "HARDLINKED",
+            Link::Reflink => "REFLINKED",
+        },
+        keep.display()
+    ))
+}
+
+/// Link `file` to `keep` under the first unused temporary name next to it (`file.fhc-dedupe`,
+/// then `file.fhc-dedupe.1`, ...) and return that name
+fn temp_link(keep: &Path, file: &Path, link: Link) -> Result<PathBuf> {
+    for i in 0..100 {
+        let mut temp = file.as_os_str().to_owned();
+        temp.push(".fhc-dedupe");
+        if i > 0 {
+            temp.push(format!(".{i}"));
+        }
+        let temp = PathBuf::from(temp);
+        let result = match link {
+            Link::Hardlink => hard_link(keep, &temp),
+            Link::Reflink => reflink(keep, &temp, file),
+        };
+        match result {
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            result => return result.map(|()| temp).map_err(Into::into),
+        }
+    }
+    bail!("no unused temporary name")
+}
+
+/// Create `dst` as a copy-on-write clone of `src` with the permissions of `like`, removing it
+/// again on failure
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dst: &Path, like: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    /// `_IOW(0x94, 9, int)`
+    const FICLONE: std::ffi::c_ulong = 0x4004_9409;
+
+    unsafe extern "C" {
+        fn ioctl(fd: std::ffi::c_int, request: std::ffi::c_ulong, ...) -> std::ffi::c_int;
+    }
+
+    let src = File::open(src)?;
+    let dst_file = File::create_new(dst)?;
+    // SAFETY: both file descriptors are open for the duration of the call.
+    let result = if unsafe { ioctl(dst_file.as_raw_fd(), FICLONE, src.as_raw_fd()) } == 0 {
+        like.metadata()
+            .and_then(|metadata| dst_file.set_permissions(metadata.permissions()))
+    } else {
+        Err(io::Error::last_os_error())
+    };
+    if result.is_err() {
+        let _ = remove_file(dst);
+    }
+    result
+}
+
+/// Create a copy-on-write clone (unavailable)
+#[cfg(not(target_os = "linux"))]
+fn reflink(_src: &Path, _dst: &Path, _like: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        ErrorKind::Unsupported,
+        "reflinks are only supported on Linux",
+    ))
+}
@@ -0,0 +1,434 @@
+use {
+    crate::{Algorithm, Hash, Status, to_hex_string},
+    anyhow::{Result, anyhow, bail},
+    clap::ValueEnum,
+    std::path::Path,
+};
+
+pub(crate) const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const BASE64URL_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+const NIX_BASE32_ALPHABET: &[u8; 32] = b"0123456789abcdfghijklmnpqrsvwxyz";
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Digest encoding of new hash files and printed hashes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    /// `LABEL:hex` (lowercase)
+    #[default]
+    Hex,
+
+    /// `LABEL:HEX` (uppercase)
+    UpperHex,
+
+    /// `LABEL:base64` (standard alphabet, padded)
+    Base64,
+
+    /// `LABEL:base32` (Nix alphabet and byte order)
+    Base32,
+
+    /// `LABEL:multihash` (multibase base32, e.g. for CIDs)
+    Multihash,
+
+    /// Subresource Integrity (`sha256-base64`, `sha512-base64`; not available for BLAKE3)
+    Sri,
+}
+
+impl Encoding {
+    /**
+    Encode a labeled hash (`LABEL:hex`) as produced by [`Hash::hash_file`]
+
+    # Errors
+
+    Returns an error if the hash is malformed or the algorithm is not supported by the encoding
+    */
+    pub fn encode(self, hash: &str) -> Result<String> {
+        let (algorithm, digest) = split_hash(hash)?;
+        if self == Encoding::Sri {
+            return Ok(format!("{}-{}", sri_prefix(algorithm)?, base64(&digest)));
+        }
+        Ok(format!(
+            "{}:{}",
+            algorithm.label(),
+            self.encode_digest(algorithm, &digest)
+        ))
+    }
+
+    /// Encode a digest without the algorithm label (SRI digests are base64)
+    #[must_use]
+    pub fn encode_digest(self, algorithm: Algorithm, digest: &[u8]) -> String {
+        match self {
+            Encoding::Hex => to_hex_string(digest),
+            Encoding::UpperHex => to_hex_string(digest).to_ascii_uppercase(),
+            Encoding::Base64 | Encoding::Sri => base64(digest),
+            Encoding::Base32 => nix_base32(digest),
+            Encoding::Multihash => {
+                let mut bytes = varint(multihash_code(algorithm));
+                bytes.extend(varint(digest.len() as u64));
+                bytes.extend(digest);
+                format!("b{}", base32(&bytes))
+            }
+        }
+    }
+}
+
+/**
+Decode a digest in any supported encoding for the given algorithm
+
+Accepts hex (either case), base64 (standard or URL-safe, padding optional), Nix base32, and
+multihash in a multibase encoding (`f` hex, `b`/`B` base32, `z` base58btc, `m` base64, `u`
+base64url); the encoding is recognized by the expected digest length.
+
+# Errors
+
+Returns an error if the digest is not a valid encoding of a digest for the algorithm, naming the
+expected number of hex digits if it only has hex digits and the invalid hex digest if it has as
+many characters as a hex digest
+*/
+pub fn decode_digest(algorithm: Algorithm, encoded: &str) -> Result<Vec<u8>> {
+    let n = algorithm.digest_len();
+    let decoded = if encoded.len() == n * 2 {
+        unhex(encoded)
+    } else if encoded.len() == n.div_ceil(3) * 4 || encoded.len() == (n * 8).div_ceil(6) {
+        unbase64(encoded, BASE64_ALPHABET).or_else(|| unbase64(encoded, BASE64URL_ALPHABET))
+    } else if encoded.len() == (n * 8 - 1) / 5 + 1 {
+        unnix_base32(encoded, n)
+    } else {
+        unmultihash(algorithm, encoded)
+    };
+    decoded.filter(|digest| digest.len() == n).ok_or_else(|| {
+        if encoded.bytes().all(|b| b.is_ascii_hexdigit()) {
+            anyhow!(
+                "expected {} hex digits for {} but found {}",
+                n * 2,
+                algorithm.label(),
+                encoded.len()
+            )
+        } else if encoded.len() == n * 2 {
+            anyhow!("invalid hex digest {encoded:?}")
+        } else {
+            anyhow!(
+                "unrecognized {} digest {encoded:?} (expected hex, base64, Nix base32, or multihash)",
+                algorithm.label()
+            )
+        }
+    })
+}
+
+/**
+Convert a labeled hash (`LABEL:hex`) to a Subresource Integrity string
+
+# Errors
+
+Returns an error if the hash is malformed or is a BLAKE3 hash (SRI only supports SHA2)
+*/
+pub fn to_sri(hash: &str) -> Result<String> {
+    Encoding::Sri.encode(hash)
+}
+
+/**
+Parse Subresource Integrity metadata (one or more whitespace-separated `alg-base64[?options]`)
+
+Entries for algorithms other than SHA256 and SHA512 (e.g. `sha384`) are skipped.
+
+# Errors
+
+Returns an error if an entry's digest is malformed or if there are no supported entries
+*/
+pub fn parse_sri(integrity: &str) -> Result<Vec<(Algorithm, Vec<u8>)>> {
+    let mut r = vec![];
+    for token in integrity.split_whitespace() {
+        if let Some(digest) = parse_sri_token(token)? {
+            r.push(digest);
+        }
+    }
+    if r.is_empty() {
+        bail!("no supported SRI hash in {integrity:?}");
+    }
+    Ok(r)
+}
+
+/**
+Verify a file against Subresource Integrity metadata
+
+As in the SRI specification, only the strongest listed algorithm is used and the file passes if
+it matches any of the digests given for that algorithm.
+The file is hashed via [`file_sha256`](crate::file_sha256) or
+[`file_sha512`](crate::file_sha512).
+
+# Errors
+
+Returns an error if the metadata is invalid or not able to read the file
+*/
+pub fn verify_sri<P: AsRef<Path>>(file: P, integrity: &str) -> Result<Status> {
+    let digests = parse_sri(integrity)?;
+    let strongest = digests
+        .iter()
+        .map(|(algorithm, _)| *algorithm)
+        .max_by_key(|algorithm| algorithm.digest_len())
+        .ok_or_else(|| anyhow!("no supported SRI hash in {integrity:?}"))?;
+    let (_, hash) = Hash::from(strongest).hash_file(file)?.remove(0);
+    let (_, actual) = split_hash(&hash)?;
+    Ok(
+        if digests
+            .iter()
+            .any(|(algorithm, digest)| *algorithm == strongest && *digest == actual)
+        {
+            Status::Ok
+        } else {
+            Status::Failed
+        },
+    )
+}
+
+/**
+Parse a single SRI token (`sha256-base64[?options]`)
+
+Returns `None` if the token does not start with an SRI algorithm prefix.
+*/
+pub(crate) fn parse_sri_token(token: &str) -> Result<Option<(Algorithm, Vec<u8>)>> {
+    let Some((prefix, rest)) = token.split_once('-') else {
+        return Ok(None);
+    };
+    let algorithm = match prefix {
+        "sha256" => Algorithm::Sha256,
+        "sha512" => Algorithm::Sha512,
+        _ => return Ok(None),
+    };
+    let encoded = rest.split_once('?').map_or(rest, |(encoded, _)| encoded);
+    let digest = unbase64(encoded, BASE64_ALPHABET)
+        .ok_or_else(|| anyhow!("invalid base64 digest {encoded:?}"))?;
+    if digest.len() != algorithm.digest_len() {
+        bail!(
+            "expected {} bytes for {} but found {}",
+            algorithm.digest_len(),
+            algorithm.label(),
+            digest.len()
+        );
+    }
+    Ok(Some((algorithm, digest)))
+}
+
+/// Split a labeled hash (`LABEL:hex`) into algorithm and digest bytes
+pub(crate) fn split_hash(hash: &str) -> Result<(Algorithm, Vec<u8>)> {
+    let (label, hex) = hash
+        .split_once(':')
+        .ok_or_else(|| anyhow!("missing algorithm label in hash {hash:?}"))?;
+    let algorithm =
+        Algorithm::from_label(label).ok_or_else(|| anyhow!("unknown algorithm label {label:?}"))?;
+    let digest = unhex(hex).ok_or_else(|| anyhow!("invalid hex digest {hex:?}"))?;
+    Ok((algorithm, digest))
+}
+
+/// SRI algorithm prefix
+fn sri_prefix(algorithm: Algorithm) -> Result<&'static str> {
+    match algorithm {
+        Algorithm::Sha256 => Ok("sha256"),
+        Algorithm::Sha512 => Ok("sha512"),
+        Algorithm::Blake3 => bail!("SRI does not support BLAKE3"),
+    }
+}
+
+/// Decode a hex string
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Encode bytes as padded standard base64
+pub(crate) fn base64(bytes: &[u8]) -> String {
+    let mut r = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0_u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                r.push(char::from(
+                    BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize],
+                ));
+            } else {
+                r.push('=');
+            }
+        }
+    }
+    r
+}
+
+/// Decode base64 with the given alphabet (padding optional)
+pub(crate) fn unbase64(encoded: &str, alphabet: &[u8; 64]) -> Option<Vec<u8>> {
+    let encoded = encoded.trim_end_matches('=');
+    let mut r = vec![];
+    let mut n = 0_u32;
+    let mut bits = 0;
+    for c in encoded.bytes() {
+        let value = alphabet.iter().position(|x| *x == c)?;
+        n = n << 6 | u32::try_from(value).ok()?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            r.push(u8::try_from(n >> bits & 0xff).ok()?);
+        }
+    }
+    (bits < 6 && n & ((1 << bits) - 1) == 0).then_some(r)
+}
+
+/// Encode bytes as unpadded lowercase RFC 4648 base32
+fn base32(bytes: &[u8]) -> String {
+    let mut r = String::new();
+    let mut n = 0_u32;
+    let mut bits = 0;
+    for b in bytes {
+        n = n << 8 | u32::from(*b);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            r.push(char::from(BASE32_ALPHABET[(n >> bits & 0x1f) as usize]));
+        }
+    }
+    if bits > 0 {
+        r.push(char::from(
+            BASE32_ALPHABET[(n << (5 - bits) & 0x1f) as usize],
+        ));
+    }
+    r
+}
+
+/// Decode unpadded RFC 4648 base32 (either case)
+fn unbase32(encoded: &str) -> Option<Vec<u8>> {
+    let mut r = vec![];
+    let mut n = 0_u32;
+    let mut bits = 0;
+    for c in encoded.bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|x| *x == c.to_ascii_lowercase())?;
+        n = n << 5 | u32::try_from(value).ok()?;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            r.push(u8::try_from(n >> bits & 0xff).ok()?);
+        }
+    }
+    (n & ((1 << bits) - 1) == 0).then_some(r)
+}
+
+/// Encode bytes as Nix base32 (least significant bits last, no padding)
+fn nix_base32(bytes: &[u8]) -> String {
+    let len = (bytes.len() * 8 - 1) / 5 + 1;
+    (0..len)
+        .rev()
+        .map(|n| {
+            let (i, j) = (n * 5 / 8, n * 5 % 8);
+            let low = u16::from(bytes[i]) >> j;
+            let high = bytes.get(i + 1).map_or(0, |b| u16::from(*b) << (8 - j));
+            char::from(NIX_BASE32_ALPHABET[usize::from((low | high) & 0x1f)])
+        })
+        .collect()
+}
+
+/// Decode Nix base32 into a digest of the given length
+fn unnix_base32(encoded: &str, len: usize) -> Option<Vec<u8>> {
+    let mut r = vec![0_u8; len];
+    for (n, c) in encoded.bytes().rev().enumerate() {
+        let digit = u16::try_from(NIX_BASE32_ALPHABET.iter().position(|x| *x == c)?).ok()?;
+        let (i, j) = (n * 5 / 8, n * 5 % 8);
+        r[i] |= u8::try_from(digit << j & 0xff).ok()?;
+        let carry = digit >> (8 - j);
+        match r.get_mut(i + 1) {
+            Some(b) => *b |= u8::try_from(carry).ok()?,
+            None if carry != 0 => return None,
+            None => {}
+        }
+    }
+    Some(r)
+}
+
+/// Decode base58btc
+fn unbase58(encoded: &str) -> Option<Vec<u8>> {
+    let mut r: Vec<u8> = vec![];
+    for c in encoded.bytes() {
+        let mut carry = u32::try_from(BASE58_ALPHABET.iter().position(|x| *x == c)?).ok()?;
+        for b in r.iter_mut().rev() {
+            carry += u32::from(*b) * 58;
+            *b = u8::try_from(carry & 0xff).ok()?;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            r.insert(0, u8::try_from(carry & 0xff).ok()?);
+            carry >>= 8;
+        }
+    }
+    let zeros = encoded.bytes().take_while(|c| *c == b'1').count();
+    let mut out = vec![0; zeros];
+    out.extend(r);
+    Some(out)
+}
+
+/// Multihash code of an algorithm
+fn multihash_code(algorithm: Algorithm) -> u64 {
+    match algorithm {
+        Algorithm::Sha256 => 0x12,
+        Algorithm::Sha512 => 0x13,
+        Algorithm::Blake3 => 0x1e,
+    }
+}
+
+/// Encode an unsigned varint (LEB128)
+fn varint(mut n: u64) -> Vec<u8> {
+    let mut r = vec![];
+    loop {
+        let b = u8::try_from(n & 0x7f).unwrap_or(0);
+        n >>= 7;
+        if n == 0 {
+            r.push(b);
+            return r;
+        }
+        r.push(b | 0x80);
+    }
+}
+
+/// Decode an unsigned varint (LEB128) from the front of `bytes`
+fn unvarint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut n = 0_u64;
+    for shift in (0..64).step_by(7) {
+        let (b, rest) = bytes.split_first()?;
+        *bytes = rest;
+        n |= u64::from(b & 0x7f) << shift;
+        if b & 0x80 == 0 {
+            return Some(n);
+        }
+    }
+    None
+}
+
+/// Decode a multibase-encoded multihash and check that it is for the given algorithm
+fn unmultihash(algorithm: Algorithm, encoded: &str) -> Option<Vec<u8>> {
+    let mut chars = encoded.chars();
+    let base = chars.next()?;
+    let rest = chars.as_str();
+    let bytes = match base {
+        'f' | 'F' => unhex(rest)?,
+        'b' | 'B' => unbase32(rest)?,
+        'z' => unbase58(rest)?,
+        'm' => unbase64(rest, BASE64_ALPHABET)?,
+        'u' => unbase64(rest, BASE64URL_ALPHABET)?,
+        _ => return None,
+    };
+    let mut bytes = bytes.as_slice();
+    let code = unvarint(&mut bytes)?;
+    let len = unvarint(&mut bytes)?;
+    (code == multihash_code(algorithm) && len == bytes.len() as u64).then(|| bytes.to_vec())
+}
@@ -0,0 +1,279 @@
+use {
+    crate::{
+        Algorithm, Hash, Status,
+        encoding::{BASE64_ALPHABET, unbase64},
+        json::{self, Value, json_string},
+        sidecar::list_file,
+    },
+    anyhow::{Result, anyhow, bail},
+    rayon::prelude::*,
+    std::{
+        fmt::Write as _,
+        fs::read_to_string,
+        path::{Component, Path, PathBuf},
+    },
+};
+
+/// Type of in-toto Statements
+const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+
+/// Payload type of DSSE envelopes with an in-toto Statement
+const PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+/// Predicate type of generated statements (in-toto Release, all fields optional)
+pub const RELEASE_PREDICATE_TYPE: &str = "https://in-toto.io/attestation/release/v0.1";
+
+/**
+Generate an in-toto Statement (v1) with a subject for each file
+
+Each subject has the file's path relative to the files' common parent directory as its name
+(with `/` separators) and a digest per algorithm of `hash` (`blake3`, `sha256`, `sha512`), so
+[`check_in_toto`] verifies the statement when it is saved in that directory (e.g.
+`dist/attestation.json` for the files in `dist`).
+The predicate is empty, so the statement can be completed and signed by other supply-chain
+tooling.
+
+```json
+{"_type":"https://in-toto.io/Statement/v1","subject":[{"name":"file","digest":{"sha256":"..."}}],"predicateType":"...","predicate":{}}
+```
+
+# Errors
+
+Returns an error if not able to read any of the files or if a path is not valid UTF-8
+*/
+pub fn in_toto<P: AsRef<Path> + Sync>(
+    files: &[P],
+    hash: Hash,
+    predicate_type: &str,
+) -> Result<String> {
+    let names = subject_names(files)?;
+    let subjects = files
+        .par_iter()
+        .zip(&names)
+        .map(|(file, name)| {
+            let file = file.as_ref();
+            let digests = hash
+                .hash_file(file)
+                .map_err(|e| anyhow!("{}: {e}", file.display()))?
+                .into_iter()
+                .map(|(_, hash)| {
+                    let (label, digest) = hash.split_once(':').unwrap_or(("", &hash));
+                    format!(
+                        "{}:{}",
+                        json_string(&label.to_ascii_lowercase()),
+                        json_string(digest)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            Ok(format!(
+                "{{\"name\":{},\"digest\":{{{digests}}}}}",
+                json_string(name)
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut r = format!(
+        "{{\"_type\":{},\"subject\":[{}]",
+        json_string(STATEMENT_TYPE),
+        subjects.join(",")
+    );
+    write!(
+        r,
+        ",\"predicateType\":{},\"predicate\":{{}}}}",
+        json_string(predicate_type)
+    )?;
+    r.push('\n');
+    Ok(r)
+}
+
+/**
+Get the paths of files relative to their common parent directory (with `/` separators)
+
+# Errors
+
+Returns an error if not able to get the current directory or if a path is not valid UTF-8
+*/
+fn subject_names<P: AsRef<Path>>(files: &[P]) -> Result<Vec<String>> {
+    let files = files
+        .iter()
+        .map(|file| Ok(lexical(&std::path::absolute(file.as_ref())?)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut common = files
+        .first()
+        .and_then(|file| file.parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    for file in &files {
+        while !file.starts_with(&common) && common.pop() {}
+    }
+    files
+        .iter()
+        .map(|file| {
+            let name = file.strip_prefix(&common).unwrap_or(file);
+            Ok(name
+                .to_str()
+                .ok_or_else(|| anyhow!("{}: path is not valid UTF-8", file.display()))?
+                .replace(std::path::MAIN_SEPARATOR, "/"))
+        })
+        .collect()
+}
+
+/// Resolve `.` and `..` components of a path without accessing the file system
+fn lexical(path: &Path) -> PathBuf {
+    let mut r = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                r.pop();
+            }
+            component => r.push(component),
+        }
+    }
+    r
+}
+
+/**
+Verify local files against the subjects of an in-toto attestation
+
+The attestation may be a bare Statement, a DSSE envelope (`payloadType`
+`application/vnd.in-toto+json`), a Sigstore bundle with a DSSE envelope, or JSON Lines of any of
+these (e.g. `.intoto.jsonl`).
+Signatures are not verified; use the signer's tooling for that.
+Each subject's name is resolved relative to the directory containing the attestation and the file
+is hashed with every supported algorithm of the subject (`blake3`, `sha256`, `sha512`); other
+algorithms are ignored.
+Absolute names and names with `..` components are refused.
+Results are returned in attestation order.
+
+# Errors
+
+Returns an error if not able to read the attestation or if it is malformed
+*/
+pub fn check_in_toto<P: AsRef<Path>>(attestation: P) -> Result<Vec<Result<String>>> {
+    let attestation = attestation.as_ref();
+    check_in_toto_content(attestation, &read_to_string(attestation)?)
+}
+
+/**
+Same as [`check_in_toto`] with the content of the attestation already read, e.g. after verifying
+its signature
+
+# Errors
+
+Returns an error if the attestation is malformed
+*/
+pub(crate) fn check_in_toto_content(
+    attestation: &Path,
+    content: &str,
+) -> Result<Vec<Result<String>>> {
+    let subjects =
+        parse_subjects(content).map_err(|e| anyhow!("{}: {e}", attestation.display()))?;
+    let dir = attestation.parent().unwrap_or(Path::new(""));
+
+    Ok(subjects
+        .par_iter()
+        .map(|(name, expected)| {
+            let Some(file) = list_file(dir, name) else {
+                bail!("{}: unsafe subject name: {name}", attestation.display());
+            };
+            if expected.is_empty() {
+                bail!(
+                    "{}: no supported digest (blake3, sha256, sha512)",
+                    file.display()
+                );
+            }
+            let algorithms = expected.iter().map(|(a, _)| *a).collect::<Vec<_>>();
+            let hashes = Hash::from_algorithms(&algorithms)
+                .ok_or_else(|| anyhow!("{}: no supported digest", file.display()))?
+                .hash_file(&file)
+                .map_err(|e| anyhow!("{}: {e}", file.display()))?;
+            let status = if expected.iter().all(|(algorithm, digest)| {
+                hashes
+                    .iter()
+                    .any(|(_, hash)| *hash == format!("{}:{digest}", algorithm.label()))
+            }) {
+                Status::Ok
+            } else {
+                Status::Failed
+            };
+            Ok(format!("{}: {status}", file.display()))
+        })
+        .collect())
+}
+
+/// Subject name with its supported `(algorithm, lowercase hex digest)` pairs
+type Subject = (String, Vec<(Algorithm, String)>);
+
+/// Get the subjects of all statements in an attestation
+fn parse_subjects(content: &str) -> Result<Vec<Subject>> {
+    let documents = match json::parse(content) {
+        Ok(document) => vec![document],
+        Err(e) => content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| {
+                json::parse(line).map_err(|_| anyhow!("invalid JSON ({e}) at line {}", i + 1))
+            })
+            .collect::<Result<Vec<_>>>()?,
+    };
+
+    let mut r = vec![];
+    for document in documents {
+        let statement = statement(document)?;
+        let Some(Value::Array(subjects)) = statement.get("subject") else {
+            bail!("statement without subjects");
+        };
+        for subject in subjects {
+            let name = subject
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow!("subject without a name"))?;
+            let Some(Value::Object(digests)) = subject.get("digest") else {
+                bail!("subject {name:?} without digests");
+            };
+            let digests = digests
+                .iter()
+                .filter_map(|(algorithm, digest)| {
+                    Some((
+                        Algorithm::from_label(algorithm)?,
+                        digest.as_str()?.to_ascii_lowercase(),
+                    ))
+                })
+                .collect();
+            r.push((name.to_string(), digests));
+        }
+    }
+    Ok(r)
+}
+
+/// Unwrap the in-toto Statement of a DSSE envelope or Sigstore bundle
+fn statement(document: Value) -> Result<Value> {
+    let document = match document.get("dsseEnvelope") {
+        Some(envelope) => envelope.clone(),
+        None => document,
+    };
+    if let Some(payload) = document.get("payload") {
+        let payload_type = document.get("payloadType").and_then(Value::as_str);
+        if payload_type != Some(PAYLOAD_TYPE) {
+            bail!("unsupported DSSE payload type {payload_type:?}");
+        }
+        let payload = payload
+            .as_str()
+            .and_then(|payload| unbase64(payload, BASE64_ALPHABET))
+            .ok_or_else(|| anyhow!("invalid DSSE payload"))?;
+        return statement(json::parse(&String::from_utf8(payload)?)?);
+    }
+    match document.get("_type").and_then(Value::as_str) {
+        Some(t) if t.starts_with("https://in-toto.io/Statement/") => Ok(document),
+        t => bail!("not an in-toto Statement (type {t:?})"),
+    }
+}
+
+/// Check whether content looks like an in-toto attestation
+pub(crate) fn is_in_toto(content: &str) -> bool {
+    content.trim_start().starts_with('{')
+        && (content.contains("https://in-toto.io/Statement/") || content.contains(PAYLOAD_TYPE))
+}
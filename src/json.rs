@@ -0,0 +1,256 @@
+use {
+    anyhow::{Result, anyhow, bail},
+    std::fmt::Write as _,
+};
+
+/// Parsed JSON value
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Get the value of a key of an object
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Get a string value
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// Encode a JSON string
+pub(crate) fn json_string(s: &str) -> String {
+    let mut r = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => r.push_str("\\\""),
+            '\\' => r.push_str("\\\\"),
+            '\n' => r.push_str("\\n"),
+            '\r' => r.push_str("\\r"),
+            '\t' => r.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(r, "\\u{:04x}", u32::from(c));
+            }
+            c => r.push(c),
+        }
+    }
+    r.push('"');
+    r
+}
+
+/**
+Parse a JSON document
+
+# Errors
+
+Returns an error if the input is not a single valid JSON value
+*/
+pub(crate) fn parse(input: &str) -> Result<Value> {
+    let mut parser = Parser {
+        input: input.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value(0)?;
+    parser.whitespace();
+    if parser.pos < parser.input.len() {
+        bail!("trailing characters at byte {}", parser.pos);
+    }
+    Ok(value)
+}
+
+/// Maximum nesting depth of arrays and objects
+const MAX_DEPTH: usize = 128;
+
+/// Recursive descent JSON parser
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn whitespace(&mut self) {
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r'))
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        self.whitespace();
+        if self.input.get(self.pos) != Some(&byte) {
+            bail!("expected {:?} at byte {}", char::from(byte), self.pos);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, literal: &str, value: Value) -> Result<Value> {
+        if !self.input[self.pos..].starts_with(literal.as_bytes()) {
+            bail!("invalid literal at byte {}", self.pos);
+        }
+        self.pos += literal.len();
+        Ok(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("nested too deeply at byte {}", self.pos);
+        }
+        self.whitespace();
+        match self.input.get(self.pos) {
+            Some(b'{') => self.object(depth),
+            Some(b'[') => self.array(depth),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => bail!("unexpected input at byte {}", self.pos),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value> {
+        self.pos += 1;
+        let mut members = vec![];
+        self.whitespace();
+        if self.input.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.whitespace();
+            if self.input.get(self.pos) != Some(&b'"') {
+                bail!("expected a key at byte {}", self.pos);
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            members.push((key, self.value(depth + 1)?));
+            self.whitespace();
+            match self.input.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => bail!("expected ',' or '}}' at byte {}", self.pos),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value> {
+        self.pos += 1;
+        let mut items = vec![];
+        self.whitespace();
+        if self.input.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.whitespace();
+            match self.input.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => bail!("expected ',' or ']' at byte {}", self.pos),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(|b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+        {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.input[start..self.pos])?
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| anyhow!("invalid number at byte {start}"))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.pos += 1;
+        let mut r = vec![];
+        loop {
+            match self.input.get(self.pos) {
+                None => bail!("unterminated string"),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(String::from_utf8(r)?);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = match self.input.get(self.pos) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => bail!("invalid escape at byte {}", self.pos),
+                    };
+                    self.pos += 1;
+                    r.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(b) if *b < b' ' => bail!("control character in string at byte {}", self.pos),
+                Some(b) => {
+                    r.push(*b);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Decode a `\uXXXX` escape (and a following low surrogate), leaving `pos` on its last byte
+    fn unicode_escape(&mut self) -> Result<char> {
+        let hex = |parser: &Self, at: usize| {
+            parser
+                .input
+                .get(at..at + 4)
+                .and_then(|x| std::str::from_utf8(x).ok())
+                .and_then(|x| u32::from_str_radix(x, 16).ok())
+                .ok_or_else(|| anyhow!("invalid unicode escape at byte {at}"))
+        };
+        let high = hex(self, self.pos + 1)?;
+        self.pos += 4;
+        let code = if (0xd800..0xdc00).contains(&high)
+            && self.input.get(self.pos + 1..self.pos + 3) == Some(b"\\u")
+        {
+            let low = hex(self, self.pos + 3)?;
+            if !(0xdc00..0xe000).contains(&low) {
+                bail!("invalid surrogate pair at byte {}", self.pos);
+            }
+            self.pos += 6;
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| anyhow!("invalid unicode escape at byte {}", self.pos))
+    }
+}
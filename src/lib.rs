@@ -1,21 +1,127 @@
 use {
     anyhow::{Result, anyhow},
     clap::ValueEnum,
-    rayon::prelude::*,
+    rayon::{ThreadPool, ThreadPoolBuilder, prelude::*},
     sha2::{Digest, Sha256, Sha512, digest::DynDigest},
     std::{
+        collections::BTreeMap,
         fmt::Write as _,
         fs::File,
-        io::{BufRead, BufReader, Read, Write, copy},
-        path::Path,
+        io::{Read, Write},
+        path::{Path, PathBuf},
+        time::UNIX_EPOCH,
     },
 };
 
+#[cfg(feature = "async")]
+mod asynchronous;
+
+#[cfg(all(unix, feature = "xattr"))]
+mod cache;
+
+#[cfg(all(unix, feature = "xattr"))]
+pub use cache::cached_hashes;
+
+mod chunks;
+mod database;
+mod diff;
+mod dupes;
+mod encoding;
+mod in_toto;
+mod json;
+mod manifest;
+
+#[cfg(feature = "minisign")]
+mod minisig;
+
+#[cfg(feature = "minisign")]
+pub use {
+    minisig::{sign_minisign, verify_minisign},
+    minisign,
+};
+
+mod mtree;
+
+#[cfg(feature = "openpgp")]
+mod openpgp;
+
+#[cfg(feature = "openpgp")]
+pub use {
+    openpgp::{check_list_openpgp, load_keyring, verify_openpgp},
+    pgp,
+};
+
+#[cfg(feature = "bao")]
+mod outboard;
+
+#[cfg(feature = "bao")]
+pub use outboard::{decode_slice, extract_slice, verify_slice, write_outboard};
+
+mod sidecar;
+
+#[cfg(feature = "ssh")]
+mod sshsig;
+
+#[cfg(feature = "ssh")]
+pub use {
+    ssh_key,
+    sshsig::{AllowedSigner, check_list_ssh, parse_allowed_signers, sign_ssh, verify_ssh},
+};
+
+mod tree;
+
+pub use chunks::{Chunks, file_chunks};
+
+pub use database::{Database, Event, Record};
+
+pub use diff::{Difference, diff, diff_manifest};
+
+pub use dupes::{DuplicateSet, Link, dedupe, duplicates_json, duplicates_text, find_duplicates};
+
+pub use encoding::{Encoding, decode_digest, parse_sri, to_sri, verify_sri};
+
+pub use in_toto::{RELEASE_PREDICATE_TYPE, check_in_toto, in_toto};
+
+pub use manifest::{Manifest, check_hashdeep, check_sfv, file_crc32, hashdeep};
+
+pub use mtree::{MtreeEntry, check_mtree, mtree, parse_mtree};
+
+pub use sidecar::{Algorithm, Entry, Format, check_list, parse_sidecar, parse_sidecar_file};
+
+pub use tree::tree_digest;
+
+#[cfg(target_os = "linux")]
+mod watch;
+
+#[cfg(target_os = "linux")]
+pub use watch::{Action, WatchEvent, Watcher};
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub use uring::io_uring;
+
 #[cfg(test)]
 mod tests;
 
 const BUFFER_SIZE: usize = 4096;
 
+/// Files at least this large are hashed with multiple threads by [`largest_first`]
+#[cfg(not(test))]
+const LARGE_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Small enough for tests to hash large files with multiple threads in several blocks
+#[cfg(test)]
+const LARGE_FILE_SIZE: u64 = 64 * 1024;
+
+/// Block size for hashing large files with multiple threads
+#[cfg(not(test))]
+const LARGE_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+#[cfg(test)]
+const LARGE_BUFFER_SIZE: usize = 16 * 1024;
+
 /// Hash algorithm
 #[derive(Clone, Copy, Debug, clap::ValueEnum)]
 pub enum Hash {
@@ -55,32 +161,285 @@ impl Hash {
 
     If the hash file does not exist, hash the file, save the hash file, and return the result.
 
+    With [`Options::dry_run`], hash the file and return the hash(es) without accessing hash files.
+
     # Errors
 
     Returns an error if not able to process the given file
     */
-    #[allow(clippy::missing_panics_doc)]
     pub fn process_file<P: AsRef<Path>>(&self, file: P) -> Result<String> {
+        Options::from(*self).process_file(file)
+    }
+
+    /**
+    Get the expected hash(es) from hash file(s)
+
+    # Errors
+
+    Returns an error if not able to read a hash file, if a hash file is malformed, or if a hash
+    file has no entry for the file
+    */
+    pub fn expected<P: AsRef<Path>>(&self, file: P) -> Result<Vec<(String, String)>> {
         let file = file.as_ref();
 
-        // Calculate the hashes
-        let hashes = self.hash_file(file)?;
+        let mut r = vec![];
+
+        for algorithm in self.algorithms() {
+            let ckfile = format!("{}.{}", file.display(), algorithm.extension());
+            let entries = parse_sidecar_file(&ckfile, Some(algorithm))?;
+            let Some(entry) = entries.iter().find(|entry| entry.matches(file)) else {
+                return Err(anyhow!("{ckfile}: no hash for {}", file.display()));
+            };
+            r.push((ckfile, entry.hash()));
+        }
+
+        Ok(r)
+    }
+}
+
+/// Options for processing files
+#[derive(Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Options {
+    /// Hash algorithm(s)
+    pub hash: Hash,
+
+    /// Format of new hash files
+    pub format: Format,
+
+    /// Digest encoding of new hash files and printed hashes
+    pub encoding: Encoding,
+
+    /// Only return the hash(es) without reading or writing hash files
+    pub dry_run: bool,
+
+    /// Record per-chunk digests of this size in new hash files (see [`file_chunks`])
+    pub chunk_size: Option<u64>,
+
+    /// Record each file's size and modification time in new hash files, so that failed
+    /// verifications tell edits from possible corruption (see [`Entry::failure`])
+    pub bit_rot: bool,
+
+    /// Sign new hash files with this key (see [`sign_minisign`])
+    #[cfg(feature = "minisign")]
+    pub signing_key: Option<minisign::SecretKey>,
+
+    /// Refuse existing hash files without a valid signature by this key (see [`verify_minisign`])
+    #[cfg(feature = "minisign")]
+    pub trusted_key: Option<minisign::PublicKey>,
+
+    /// Also write a Bao outboard encoding of each file (see [`write_outboard`]) if its hash
+    /// file(s) are new or it verifies OK; requires BLAKE3
+    #[cfg(feature = "bao")]
+    pub bao: bool,
+
+    /// Trust digests cached in `user.fhc.*` extended attributes for files whose metadata is
+    /// unchanged, and cache newly calculated digests (see [`cached_hashes`]); the `io-uring`
+    /// approach and the async functions always hash
+    #[cfg(all(unix, feature = "xattr"))]
+    pub cache: bool,
+
+    /// Ignore cached digests and hash every file, still updating the cache
+    #[cfg(all(unix, feature = "xattr"))]
+    pub paranoid: bool,
+}
+
+impl From<Hash> for Options {
+    fn from(hash: Hash) -> Options {
+        Options {
+            hash,
+            format: Format::default(),
+            encoding: Encoding::default(),
+            dry_run: false,
+            chunk_size: None,
+            bit_rot: false,
+            #[cfg(feature = "minisign")]
+            signing_key: None,
+            #[cfg(feature = "minisign")]
+            trusted_key: None,
+            #[cfg(feature = "bao")]
+            bao: false,
+            #[cfg(all(unix, feature = "xattr"))]
+            cache: false,
+            #[cfg(all(unix, feature = "xattr"))]
+            paranoid: false,
+        }
+    }
+}
+
+impl Options {
+    /**
+    Process a file
+
+    If the hash file exists, hash the file, compare hashes, and return the result.
+
+    If the hash file does not exist, hash the file, save the hash file, and return the result.
+
+    # Errors
+
+    Returns an error if not able to process the given file
+    */
+    pub fn process_file<P: AsRef<Path>>(&self, file: P) -> Result<String> {
+        self.process_file_hashes(file.as_ref())
+            .map(|(result, _)| result)
+    }
+
+    /**
+    Same as [`Options::process_file`], but also return the file's `(ckfile, hash)` tuples
+
+    # Errors
+
+    Returns an error if not able to process the given file
+    */
+    pub(crate) fn process_file_hashes(
+        &self,
+        file: &Path,
+    ) -> Result<(String, Vec<(String, String)>)> {
+        // Calculate the hashes, along with the chunk digests (if new hash files will record them)
+        // and the Bao outboard (if any) in the same read
+        let save = !self.dry_run && !self.ckfiles(file).iter().all(|x| x.exists());
+        let chunk_size = self.chunk_size.filter(|_| save);
+        let mut chunks = None;
+        #[cfg(feature = "bao")]
+        let mut outboard = None;
+        let hashes = self.hashes(file, || {
+            #[cfg(feature = "bao")]
+            if let Some(mut new) = self.outboard(file)? {
+                let (hashes, file_chunks) =
+                    chunks::hash_into(file, self.hash, chunk_size, &mut new)?;
+                chunks = file_chunks;
+                outboard = Some(new);
+                return Ok(hashes);
+            }
+            match chunk_size {
+                Some(chunk_size) => {
+                    let (hashes, file_chunks) = file_chunks(file, self.hash, chunk_size)?;
+                    chunks = Some(file_chunks);
+                    Ok(hashes)
+                }
+                None => self.hash.hash_file(file),
+            }
+        })?;
+
+        let result = self.check_or_save_with(file, &hashes, chunks, "")?;
+        #[cfg(feature = "bao")]
+        self.finish_outboard(file, &hashes, save, outboard)?;
+        Ok((result, hashes))
+    }
+
+    /// Get the hash file paths of a file (one per algorithm)
+    pub(crate) fn ckfiles(&self, file: &Path) -> Vec<PathBuf> {
+        self.hash
+            .algorithms()
+            .into_iter()
+            .map(|algorithm| PathBuf::from(format!("{}.{}", file.display(), algorithm.extension())))
+            .collect()
+    }
+
+    /**
+    Hash a file with the given function, unless its digests are cached (see [`Options::cache`])
+
+    # Errors
+
+    Returns an error if not able to read the given file
+    */
+    #[cfg_attr(not(all(unix, feature = "xattr")), allow(unused_variables))]
+    fn hashes<F: FnOnce() -> Result<Vec<(String, String)>>>(
+        &self,
+        file: &Path,
+        hash_file: F,
+    ) -> Result<Vec<(String, String)>> {
+        #[cfg(all(unix, feature = "xattr"))]
+        if self.cache {
+            return cache::hashes(file, self.hash, self.paranoid, hash_file);
+        }
+        hash_file()
+    }
+
+    /**
+    Compare the given hashes to the hash file(s) or save them to new hash file(s)
+
+    With [`Options::bao`], a missing outboard is then written in a separate read.
+
+    # Errors
+
+    Returns an error if not able to read or write the hash file(s)
+    */
+    fn check_or_save(&self, file: &Path, hashes: &[(String, String)]) -> Result<String> {
+        #[cfg(feature = "bao")]
+        let save = !self.dry_run && !self.ckfiles(file).iter().all(|x| x.exists());
+        let result = self.check_or_save_with(file, hashes, None, "")?;
+        #[cfg(feature = "bao")]
+        self.finish_outboard(file, hashes, save, None)?;
+        Ok(result)
+    }
+
+    /**
+    Same as [`Options::check_or_save`] with chunk digests already calculated, if any, and comment
+    line(s) to append to new hash files
 
-        Ok(if let Ok(expected) = self.expected(file) {
+    # Errors
+
+    Returns an error if not able to read or write the hash file(s)
+    */
+    #[allow(clippy::missing_panics_doc)]
+    fn check_or_save_with(
+        &self,
+        file: &Path,
+        hashes: &[(String, String)],
+        chunks: Option<Vec<Chunks>>,
+        comment: &str,
+    ) -> Result<String> {
+        Ok(if self.dry_run {
+            hashes
+                .iter()
+                .map(|(_, hash)| {
+                    Ok(format!(
+                        "{}  {}",
+                        self.encoding.encode(hash)?,
+                        file.display()
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?
+                .join("\n")
+        } else if hashes.iter().all(|(ckfile, _)| Path::new(ckfile).exists()) {
             // The hash file(s) exist, so verify them and return the result.
-            format!(
-                "{}: {}",
-                file.display(),
-                if hashes == expected { "OK" } else { "FAILED" },
-            )
+            #[cfg(feature = "minisign")]
+            self.verify_signatures(hashes)?;
+            let (status, failed) = self.status(file, hashes)?;
+            let corruption = match failed {
+                Some(entry) => chunks::corruption(file, &entry)?,
+                None => String::new(),
+            };
+            format!("{}: {status}{corruption}", file.display())
         } else {
             // The hash file(s) do not exist, so save the hash(es) to new hash file(s), and return it.
-            let mut r = vec![];
-            for (ckfile, hash) in &hashes {
-                let mut ckfile = File::create(ckfile)?;
+            let chunks = match (self.chunk_size, chunks) {
+                (Some(_), Some(chunks)) => Some(chunks),
+                (Some(chunk_size), None) => Some(file_chunks(file, self.hash, chunk_size)?.1),
+                (None, _) => None,
+            };
+            // Build every hash file before creating any, so that an unsupported encoding and
+            // format combination does not leave empty hash files behind.
+            let mut contents = vec![];
+            for (i, (path, hash)) in hashes.iter().enumerate() {
+                let hash = self.encoding.encode(hash)?;
                 let filename = file.file_name().unwrap().to_str().unwrap();
-                let content = format!("{hash}  {filename}\n");
-                ckfile.write_all(content.as_bytes())?;
+                let mut content = self.format.line(&hash, filename)?;
+                content.push_str(comment);
+                if self.bit_rot {
+                    content.push_str(&sidecar::stat_comment(file)?);
+                }
+                if let Some(chunks) = &chunks {
+                    content.push_str(&chunks[i].lines());
+                }
+                contents.push((path, hash, content));
+            }
+            let mut r = vec![];
+            for (path, hash, content) in contents {
+                File::create(path)?.write_all(content.as_bytes())?;
+                #[cfg(feature = "minisign")]
+                self.sign(path)?;
                 r.push(format!("{hash}  {}", file.display()));
             }
             r.join("\n")
@@ -88,54 +447,66 @@ impl Hash {
     }
 
     /**
-    Get the expected hash(es) from hash file(s)
+    Compare the given hashes to the hash file(s)
 
-    # Panics
-
-    Panics if not able to get the expected hash from the hash file(s)
+    The entry for the file is looked up by filename, so a hash file may list multiple files; if a
+    hash file has no entry for the file, the result is [`Status::FilenameMismatch`].
+    Also returns a failed entry, preferring one with chunk digests, and classifies the failure
+    with [`Entry::failure`].
 
     # Errors
 
-    Returns an error if not able to get the expected hash from the hash file(s)
+    Returns an error if not able to read a hash file or if a hash file is malformed
     */
-    pub fn expected<P: AsRef<Path>>(&self, file: P) -> Result<Vec<(String, String)>> {
-        let file = file.as_ref();
+    fn status(&self, file: &Path, hashes: &[(String, String)]) -> Result<(Status, Option<Entry>)> {
+        let mut failed: Option<Entry> = None;
+        for ((ckfile, hash), algorithm) in hashes.iter().zip(self.hash.algorithms()) {
+            let entries = parse_sidecar_file(ckfile, Some(algorithm))?;
+            match entries.into_iter().find(|entry| entry.matches(file)) {
+                None => return Ok((Status::FilenameMismatch, None)),
+                Some(entry) if entry.hash() != *hash => {
+                    if failed.as_ref().is_none_or(|failed| failed.chunks.is_none()) {
+                        failed = Some(entry);
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(match failed {
+            Some(entry) => (entry.failure(file), Some(entry)),
+            None => (Status::Ok, None),
+        })
+    }
+}
 
-        let mut r = vec![];
+/// Result of verifying a file against its hash file(s)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// Hashes match
+    Ok,
 
-        let ckfiles = match self {
-            Hash::Blake3 => vec![format!("{}.b3", file.display())],
-            Hash::Sha256 => vec![format!("{}.sha256", file.display())],
-            Hash::Sha512 => vec![format!("{}.sha512", file.display())],
-            Hash::Blake3Sha256 => vec![
-                format!("{}.b3", file.display()),
-                format!("{}.sha256", file.display()),
-            ],
-            Hash::Blake3Sha512 => vec![
-                format!("{}.b3", file.display()),
-                format!("{}.sha512", file.display()),
-            ],
-            Hash::Sha256Sha512 => vec![
-                format!("{}.sha256", file.display()),
-                format!("{}.sha512", file.display()),
-            ],
-            Hash::All => vec![
-                format!("{}.b3", file.display()),
-                format!("{}.sha256", file.display()),
-                format!("{}.sha512", file.display()),
-            ],
-        };
+    /// Hashes do not match
+    Failed,
 
-        for ckfile in ckfiles {
-            let mut reader = BufReader::new(File::open(&ckfile)?);
-            let mut expected = String::new();
-            reader.read_line(&mut expected)?;
-            expected = expected.lines().next().unwrap().to_string();
-            expected.truncate(expected.find(' ').unwrap_or(expected.len()));
-            r.push((ckfile, expected));
-        }
+    /// Hashes do not match and the recorded size or modification time changed
+    Modified,
 
-        Ok(r)
+    /// Hashes do not match although the recorded size and modification time are unchanged
+    Corrupted,
+
+    /// Hash file does not record this file's name
+    FilenameMismatch,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Status::Ok => "OK",
+            Status::Failed => "FAILED",
+            Status::Modified => "FAILED (modified)",
+            Status::Corrupted => "FAILED (possible corruption)",
+            Status::FilenameMismatch => "FILENAME MISMATCH",
+        })
     }
 }
 
@@ -147,24 +518,7 @@ Calculate the SHA256 hash for a file
 Returns an error if not able to read the given file
 */
 pub fn file_sha256<P: AsRef<Path>>(file: P) -> Result<Vec<(String, String)>> {
-    let file = file.as_ref();
-    let mut f = BufReader::new(File::open(file)?);
-    let mut hasher = Sha256::new();
-    let mut buffer = [0; BUFFER_SIZE];
-    loop {
-        let bytes_read = f.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
-        }
-        Digest::update(&mut hasher, &buffer[..bytes_read]);
-    }
-    let mut buffer = vec![0; hasher.output_size()];
-    DynDigest::finalize_into(hasher, &mut buffer)?;
-    let hash = to_hex_string(&buffer);
-    Ok(vec![(
-        format!("{}.sha256", file.display()),
-        format!("SHA256:{hash}"),
-    )])
+    file_hash(file.as_ref(), Hash::Sha256)
 }
 
 /**
@@ -175,24 +529,7 @@ Calculate the SHA512 hash for a file
 Returns an error if not able to read the given file
 */
 pub fn file_sha512<P: AsRef<Path>>(file: P) -> Result<Vec<(String, String)>> {
-    let file = file.as_ref();
-    let mut f = File::open(file)?;
-    let mut hasher = Sha512::new();
-    let mut buffer = [0; BUFFER_SIZE];
-    loop {
-        let bytes_read = f.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
-        }
-        Digest::update(&mut hasher, &buffer[..bytes_read]);
-    }
-    let mut buffer = vec![0; hasher.output_size()];
-    DynDigest::finalize_into(hasher, &mut buffer)?;
-    let hash = to_hex_string(&buffer);
-    Ok(vec![(
-        format!("{}.sha512", file.display()),
-        format!("SHA512:{hash}"),
-    )])
+    file_hash(file.as_ref(), Hash::Sha512)
 }
 
 /**
@@ -203,14 +540,7 @@ Calculate the BLAKE3 hash for a file
 Returns an error if not able to read the given file
 */
 pub fn file_blake3<P: AsRef<Path>>(file: P) -> Result<Vec<(String, String)>> {
-    let file = file.as_ref();
-    let mut f = File::open(file)?;
-    let mut hasher = blake3::Hasher::new();
-    copy(&mut f, &mut hasher)?;
-    Ok(vec![(
-        format!("{}.b3", file.display()),
-        format!("BLAKE3:{}", hasher.finalize()),
-    )])
+    file_hash(file.as_ref(), Hash::Blake3)
 }
 
 /**
@@ -221,37 +551,7 @@ Calculate the BLAKE3 and SHA256 hashes for a file
 Returns an error if not able to read the given file
 */
 pub fn file_blake3_sha256<P: AsRef<Path>>(file: P) -> Result<Vec<(String, String)>> {
-    let file = file.as_ref();
-    let mut f = File::open(file)?;
-
-    let mut hasher_b3 = blake3::Hasher::new();
-    let mut hasher_sha256 = Sha256::new();
-
-    let mut buffer = [0; BUFFER_SIZE];
-    loop {
-        let bytes_read = f.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
-        }
-        let buf = &buffer[..bytes_read];
-        hasher_b3.update(buf);
-        Digest::update(&mut hasher_sha256, buf);
-    }
-
-    let mut buffer = vec![0; hasher_sha256.output_size()];
-    DynDigest::finalize_into(hasher_sha256, &mut buffer)?;
-    let hash_sha256 = to_hex_string(&buffer);
-
-    Ok(vec![
-        (
-            format!("{}.b3", file.display()),
-            format!("BLAKE3:{}", hasher_b3.finalize()),
-        ),
-        (
-            format!("{}.sha256", file.display()),
-            format!("SHA256:{hash_sha256}"),
-        ),
-    ])
+    file_hash(file.as_ref(), Hash::Blake3Sha256)
 }
 
 /**
@@ -262,37 +562,7 @@ Calculate the BLAKE3 and SHA512 hashes for a file
 Returns an error if not able to read the given file
 */
 pub fn file_blake3_sha512<P: AsRef<Path>>(file: P) -> Result<Vec<(String, String)>> {
-    let file = file.as_ref();
-    let mut f = File::open(file)?;
-
-    let mut hasher_b3 = blake3::Hasher::new();
-    let mut hasher_sha512 = Sha512::new();
-
-    let mut buffer = [0; BUFFER_SIZE];
-    loop {
-        let bytes_read = f.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
-        }
-        let buf = &buffer[..bytes_read];
-        hasher_b3.update(buf);
-        Digest::update(&mut hasher_sha512, buf);
-    }
-
-    let mut buffer = vec![0; hasher_sha512.output_size()];
-    DynDigest::finalize_into(hasher_sha512, &mut buffer)?;
-    let hash_sha512 = to_hex_string(&buffer);
-
-    Ok(vec![
-        (
-            format!("{}.b3", file.display()),
-            format!("BLAKE3:{}", hasher_b3.finalize()),
-        ),
-        (
-            format!("{}.sha512", file.display()),
-            format!("SHA512:{hash_sha512}"),
-        ),
-    ])
+    file_hash(file.as_ref(), Hash::Blake3Sha512)
 }
 
 /**
@@ -303,92 +573,210 @@ Calculate the SHA256 and SHA512 hashes for a file
 Returns an error if not able to read the given file
 */
 pub fn file_sha256_sha512<P: AsRef<Path>>(file: P) -> Result<Vec<(String, String)>> {
-    let file = file.as_ref();
-    let mut f = File::open(file)?;
+    file_hash(file.as_ref(), Hash::Sha256Sha512)
+}
 
-    let mut hasher_sha256 = Sha256::new();
-    let mut hasher_sha512 = Sha512::new();
+/**
+Calculate all hashes for a file
+
+# Errors
 
+Returns an error if not able to read the given file
+*/
+pub fn file_all<P: AsRef<Path>>(file: P) -> Result<Vec<(String, String)>> {
+    file_hash(file.as_ref(), Hash::All)
+}
+
+/// Read a file into a [`Hasher`] for the given hash algorithm(s)
+fn file_hash(file: &Path, hash: Hash) -> Result<Vec<(String, String)>> {
+    let mut f = File::open(file)?;
+    let mut hasher = Hasher::new(hash);
     let mut buffer = [0; BUFFER_SIZE];
     loop {
         let bytes_read = f.read(&mut buffer)?;
         if bytes_read == 0 {
             break;
         }
-        let buf = &buffer[..bytes_read];
-        Digest::update(&mut hasher_sha256, buf);
-        Digest::update(&mut hasher_sha512, buf);
+        hasher.update(&buffer[..bytes_read]);
     }
+    hasher.finalize(file)
+}
 
-    let mut buffer = vec![0; hasher_sha256.output_size()];
-    DynDigest::finalize_into(hasher_sha256, &mut buffer)?;
-    let hash_sha256 = to_hex_string(&buffer);
+/// Get the size and modification time (nanoseconds since the Unix epoch) of a file
+pub(crate) fn stat(file: &Path) -> Result<(u64, i128)> {
+    let metadata = file
+        .metadata()
+        .map_err(|e| anyhow!("{}: {e}", file.display()))?;
+    let mtime = match metadata.modified()?.duration_since(UNIX_EPOCH) {
+        Ok(d) => i128::try_from(d.as_nanos())?,
+        Err(e) => -i128::try_from(e.duration().as_nanos())?,
+    };
+    Ok((metadata.len(), mtime))
+}
+
+/// Incremental hasher for one or more hash algorithms
+#[derive(Clone)]
+pub struct Hasher {
+    blake3: Option<blake3::Hasher>,
+    sha256: Option<Sha256>,
+    sha512: Option<Sha512>,
+}
 
-    let mut buffer = vec![0; hasher_sha512.output_size()];
-    DynDigest::finalize_into(hasher_sha512, &mut buffer)?;
-    let hash_sha512 = to_hex_string(&buffer);
+impl Hasher {
+    /// Create a new hasher for the given hash algorithm(s)
+    #[must_use]
+    pub fn new(hash: Hash) -> Hasher {
+        let (blake3, sha256, sha512) = match hash {
+            Hash::Blake3 => (true, false, false),
+            Hash::Sha256 => (false, true, false),
+            Hash::Sha512 => (false, false, true),
+            Hash::Blake3Sha256 => (true, true, false),
+            Hash::Blake3Sha512 => (true, false, true),
+            Hash::Sha256Sha512 => (false, true, true),
+            Hash::All => (true, true, true),
+        };
+        Hasher {
+            blake3: blake3.then(blake3::Hasher::new),
+            sha256: sha256.then(Sha256::new),
+            sha512: sha512.then(Sha512::new),
+        }
+    }
+
+    /// Add input bytes to the hash state
+    pub fn update(&mut self, buf: &[u8]) {
+        if let Some(hasher) = &mut self.blake3 {
+            hasher.update(buf);
+        }
+        if let Some(hasher) = &mut self.sha256 {
+            Digest::update(hasher, buf);
+        }
+        if let Some(hasher) = &mut self.sha512 {
+            Digest::update(hasher, buf);
+        }
+    }
 
-    Ok(vec![
-        (
-            format!("{}.sha256", file.display()),
-            format!("SHA256:{hash_sha256}"),
-        ),
-        (
-            format!("{}.sha512", file.display()),
-            format!("SHA512:{hash_sha512}"),
-        ),
-    ])
+    /**
+    Add input bytes to the hash state using multiple threads
+
+    BLAKE3 hashes the input across the current Rayon thread pool while the SHA hashes (which
+    cannot be split) run alongside it; only worthwhile for large inputs (at least several MiB).
+    */
+    pub fn update_rayon(&mut self, buf: &[u8]) {
+        let Hasher {
+            blake3,
+            sha256,
+            sha512,
+        } = self;
+        rayon::join(
+            || {
+                if let Some(hasher) = blake3 {
+                    hasher.update_rayon(buf);
+                }
+            },
+            || {
+                if let Some(hasher) = sha256 {
+                    Digest::update(hasher, buf);
+                }
+                if let Some(hasher) = sha512 {
+                    Digest::update(hasher, buf);
+                }
+            },
+        );
+    }
+
+    /**
+    Finalize the hash state and return the hash(es) for a file as `(ckfile, hash)` tuples
+
+    # Errors
+
+    Returns an error if not able to finalize the hash state
+    */
+    pub fn finalize<P: AsRef<Path>>(self, file: P) -> Result<Vec<(String, String)>> {
+        let file = file.as_ref();
+
+        let mut r = vec![];
+
+        if let Some(hasher) = self.blake3 {
+            r.push((
+                format!("{}.b3", file.display()),
+                format!("BLAKE3:{}", hasher.finalize()),
+            ));
+        }
+
+        if let Some(hasher) = self.sha256 {
+            let mut buffer = vec![0; hasher.output_size()];
+            DynDigest::finalize_into(hasher, &mut buffer)?;
+            r.push((
+                format!("{}.sha256", file.display()),
+                format!("SHA256:{}", to_hex_string(&buffer)),
+            ));
+        }
+
+        if let Some(hasher) = self.sha512 {
+            let mut buffer = vec![0; hasher.output_size()];
+            DynDigest::finalize_into(hasher, &mut buffer)?;
+            r.push((
+                format!("{}.sha512", file.display()),
+                format!("SHA512:{}", to_hex_string(&buffer)),
+            ));
+        }
+
+        Ok(r)
+    }
 }
 
 /**
-Calculate all hashes for a file
+What the process options do with each file: process it ([`Options`], see
+[`Options::process_file`]) or only hash it ([`HashOnly`])
+*/
+pub(crate) trait Process: Clone + Send + Sync + 'static {
+    /// Result for each file
+    type Output: std::fmt::Debug + Send + 'static;
 
-# Errors
+    /// Options with the hash algorithm(s) and digest cache settings
+    fn options(&self) -> &Options;
 
-Returns an error if not able to read the given file
-*/
-pub fn file_all<P: AsRef<Path>>(file: P) -> Result<Vec<(String, String)>> {
-    let file = file.as_ref();
-    let mut f = File::open(file)?;
+    /// Process a file
+    fn process(&self, file: &Path) -> Result<Self::Output>;
 
-    let mut hasher_b3 = blake3::Hasher::new();
-    let mut hasher_sha256 = Sha256::new();
-    let mut hasher_sha512 = Sha512::new();
+    /// Process a file whose hashes are already calculated
+    fn finish(&self, file: &Path, hashes: &[(String, String)]) -> Result<Self::Output>;
+}
 
-    let mut buffer = [0; BUFFER_SIZE];
-    loop {
-        let bytes_read = f.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
-        }
-        let buf = &buffer[..bytes_read];
-        hasher_b3.update(buf);
-        Digest::update(&mut hasher_sha256, buf);
-        Digest::update(&mut hasher_sha512, buf);
-    }
-
-    let mut buffer = vec![0; hasher_sha256.output_size()];
-    DynDigest::finalize_into(hasher_sha256, &mut buffer)?;
-    let hash_sha256 = to_hex_string(&buffer);
-
-    let mut buffer = vec![0; hasher_sha512.output_size()];
-    DynDigest::finalize_into(hasher_sha512, &mut buffer)?;
-    let hash_sha512 = to_hex_string(&buffer);
-
-    Ok(vec![
-        (
-            format!("{}.b3", file.display()),
-            format!("BLAKE3:{}", hasher_b3.finalize()),
-        ),
-        (
-            format!("{}.sha256", file.display()),
-            format!("SHA256:{hash_sha256}"),
-        ),
-        (
-            format!("{}.sha512", file.display()),
-            format!("SHA512:{hash_sha512}"),
-        ),
-    ])
+impl Process for Options {
+    type Output = String;
+
+    fn options(&self) -> &Options {
+        self
+    }
+
+    fn process(&self, file: &Path) -> Result<String> {
+        self.process_file(file)
+    }
+
+    fn finish(&self, file: &Path, hashes: &[(String, String)]) -> Result<String> {
+        self.check_or_save(file, hashes)
+    }
+}
+
+/// Only hash files (using the digest cache, if enabled) without accessing hash files
+#[derive(Clone)]
+pub(crate) struct HashOnly(pub(crate) Options);
+
+impl Process for HashOnly {
+    type Output = Vec<(String, String)>;
+
+    fn options(&self) -> &Options {
+        &self.0
+    }
+
+    fn process(&self, file: &Path) -> Result<Self::Output> {
+        self.0.hashes(file, || self.0.hash.hash_file(file))
+    }
+
+    fn finish(&self, _file: &Path, hashes: &[(String, String)]) -> Result<Self::Output> {
+        Ok(hashes.to_vec())
+    }
 }
 
 /// Approaches for processing multiple files
@@ -399,54 +787,130 @@ pub enum ProcessOption {
     SequentialIter,
     Threading,
     Messaging,
+    PerDevice,
+    LargestFirst,
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    IoUring,
 }
 
 impl ProcessOption {
-    /// Process files with the given hash
-    pub fn run<P: AsRef<Path> + Clone + Send + Sync + 'static>(
+    /// Process files with the given hash algorithm or options
+    pub fn run<P: AsRef<Path> + Clone + Send + Sync + 'static, O: Into<Options>>(
         &self,
         files: &[P],
-        hash: Hash,
+        options: O,
     ) -> Vec<Result<String>> {
+        self.run_by(files, &options.into())
+    }
+
+    /// Process files with any [`Process`] (e.g. only hash them via [`HashOnly`])
+    pub(crate) fn run_by<P: AsRef<Path> + Clone + Send + Sync + 'static, T: Process>(
+        &self,
+        files: &[P],
+        process: &T,
+    ) -> Vec<Result<T::Output>> {
         match self {
-            ProcessOption::SequentialForLoop => seq_for_loop(files, hash),
-            ProcessOption::SequentialIter => seq_iter(files, hash),
-            ProcessOption::Threading => threading(files, hash),
-            ProcessOption::Messaging => messaging(files, hash),
-            ProcessOption::RayonParIter => rayon_par_iter(files, hash),
+            ProcessOption::SequentialForLoop => seq_for_loop_by(files, process),
+            ProcessOption::SequentialIter => seq_iter_by(files, process),
+            ProcessOption::Threading => threading_by(files, process),
+            ProcessOption::Messaging => messaging_by(files, process),
+            ProcessOption::RayonParIter => rayon_par_iter_by(files, process),
+            ProcessOption::PerDevice => per_device_by(files, process),
+            ProcessOption::LargestFirst => largest_first_by(files, process),
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            ProcessOption::IoUring => uring::io_uring_by(files, process),
         }
     }
+
+    /**
+    Process files with the given hash algorithm or options in the given Rayon thread pool
+
+    Parallel work is isolated to `pool` instead of the global Rayon thread pool.
+    */
+    pub fn run_in<P: AsRef<Path> + Clone + Send + Sync + 'static, O: Into<Options>>(
+        &self,
+        files: &[P],
+        options: O,
+        pool: &ThreadPool,
+    ) -> Vec<Result<String>> {
+        let options = options.into();
+        pool.install(|| self.run(files, options))
+    }
+}
+
+/**
+Build a dedicated Rayon thread pool with the given number of threads
+
+Zero threads selects the Rayon default (one per logical CPU).
+
+# Errors
+
+Returns an error if not able to build the thread pool
+*/
+pub fn thread_pool(jobs: usize) -> Result<ThreadPool> {
+    Ok(ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .thread_name(|i| format!("fhc-{i}"))
+        .build()?)
 }
 
 /// Process files with the given hash algorithm via seqential for loop
-pub fn seq_for_loop<P: AsRef<Path> + Clone + Send + Sync + 'static>(
+pub fn seq_for_loop<P: AsRef<Path> + Clone + Send + Sync + 'static, O: Into<Options>>(
     files: &[P],
-    hash: Hash,
+    options: O,
 ) -> Vec<Result<String>> {
+    seq_for_loop_by(files, &options.into())
+}
+
+/// Same as [`seq_for_loop`] with any [`Process`]
+fn seq_for_loop_by<P: AsRef<Path> + Clone + Send + Sync + 'static, T: Process>(
+    files: &[P],
+    process: &T,
+) -> Vec<Result<T::Output>> {
     let mut r = vec![];
     for file in files {
-        r.push(hash.process_file(file));
+        r.push(process.process(file.as_ref()));
     }
     r
 }
 
 /// Process files with the given hash algorithm via seqential iterator
-pub fn seq_iter<P: AsRef<Path> + Clone + Send + Sync + 'static>(
+pub fn seq_iter<P: AsRef<Path> + Clone + Send + Sync + 'static, O: Into<Options>>(
     files: &[P],
-    hash: Hash,
+    options: O,
 ) -> Vec<Result<String>> {
-    files.iter().map(|file| hash.process_file(file)).collect()
+    seq_iter_by(files, &options.into())
+}
+
+/// Same as [`seq_iter`] with any [`Process`]
+fn seq_iter_by<P: AsRef<Path> + Clone + Send + Sync + 'static, T: Process>(
+    files: &[P],
+    process: &T,
+) -> Vec<Result<T::Output>> {
+    files
+        .iter()
+        .map(|file| process.process(file.as_ref()))
+        .collect()
 }
 
 /// Process files with the given hash algorithm via threading
-pub fn threading<P: AsRef<Path> + Clone + Send + Sync + 'static>(
+pub fn threading<P: AsRef<Path> + Clone + Send + Sync + 'static, O: Into<Options>>(
     files: &[P],
-    hash: Hash,
+    options: O,
 ) -> Vec<Result<String>> {
+    threading_by(files, &options.into())
+}
+
+/// Same as [`threading`] with any [`Process`]
+fn threading_by<P: AsRef<Path> + Clone + Send + Sync + 'static, T: Process>(
+    files: &[P],
+    process: &T,
+) -> Vec<Result<T::Output>> {
     let mut r = vec![];
     let mut handles = vec![];
-    for file in files.iter().cloned() {
-        handles.push(std::thread::spawn(move || hash.process_file(file)));
+    for file in files {
+        let (process, file) = (process.clone(), file.clone());
+        handles.push(std::thread::spawn(move || process.process(file.as_ref())));
     }
     for handle in handles {
         match handle.join() {
@@ -468,16 +932,25 @@ Process files with the given hash algorithm via messaging
 
 Panics if not able to spawn a thread
 */
-pub fn messaging<P: AsRef<Path> + Clone + Send + Sync + 'static>(
+pub fn messaging<P: AsRef<Path> + Clone + Send + Sync + 'static, O: Into<Options>>(
     files: &[P],
-    hash: Hash,
+    options: O,
 ) -> Vec<Result<String>> {
+    messaging_by(files, &options.into())
+}
+
+/// Same as [`messaging`] with any [`Process`]
+fn messaging_by<P: AsRef<Path> + Clone + Send + Sync + 'static, T: Process>(
+    files: &[P],
+    process: &T,
+) -> Vec<Result<T::Output>> {
     let mut r = vec![];
     let mut rxs = vec![];
-    for file in files.iter().cloned() {
+    for file in files {
         let (tx, rx) = std::sync::mpsc::channel();
         rxs.push(rx);
-        std::thread::spawn(move || tx.send(hash.process_file(file)).unwrap());
+        let (process, file) = (process.clone(), file.clone());
+        std::thread::spawn(move || tx.send(process.process(file.as_ref())).unwrap());
     }
     for rx in rxs {
         match rx.recv() {
@@ -493,16 +966,147 @@ pub fn messaging<P: AsRef<Path> + Clone + Send + Sync + 'static>(
 }
 
 /// Process files with the given hash algorithm via Rayon parallel iterator
-pub fn rayon_par_iter<P: AsRef<Path> + Clone + Send + Sync + 'static>(
+pub fn rayon_par_iter<P: AsRef<Path> + Clone + Send + Sync + 'static, O: Into<Options>>(
     files: &[P],
-    hash: Hash,
+    options: O,
 ) -> Vec<Result<String>> {
+    rayon_par_iter_by(files, &options.into())
+}
+
+/// Same as [`rayon_par_iter`] with any [`Process`]
+fn rayon_par_iter_by<P: AsRef<Path> + Clone + Send + Sync + 'static, T: Process>(
+    files: &[P],
+    process: &T,
+) -> Vec<Result<T::Output>> {
     files
         .par_iter()
-        .map(|file| hash.process_file(file))
+        .map(|file| process.process(file.as_ref()))
         .collect()
 }
 
+/**
+Process files with the given hash algorithm grouped by device
+
+Files on the same device (`st_dev`) are processed sequentially in inode order (a cheap
+approximation of physical order) to avoid seeking back and forth on spinning disks, while
+different devices are processed in parallel via Rayon.
+Files that cannot be stat'ed form their own group.
+Results are returned in input order.
+*/
+pub fn per_device<P: AsRef<Path> + Clone + Send + Sync + 'static, O: Into<Options>>(
+    files: &[P],
+    options: O,
+) -> Vec<Result<String>> {
+    per_device_by(files, &options.into())
+}
+
+/// Same as [`per_device`] with any [`Process`]
+fn per_device_by<P: AsRef<Path> + Clone + Send + Sync + 'static, T: Process>(
+    files: &[P],
+    process: &T,
+) -> Vec<Result<T::Output>> {
+    let mut devices: BTreeMap<Option<u64>, Vec<(u64, usize)>> = BTreeMap::new();
+    for (i, file) in files.iter().enumerate() {
+        let (device, inode) = match device_inode(file.as_ref()) {
+            Some((device, inode)) => (Some(device), inode),
+            None => (None, 0),
+        };
+        devices.entry(device).or_default().push((inode, i));
+    }
+
+    let mut r = devices
+        .into_par_iter()
+        .flat_map_iter(|(_, mut group)| {
+            group.sort_unstable();
+            group
+                .into_iter()
+                .map(|(_, i)| (i, process.process(files[i].as_ref())))
+        })
+        .collect::<Vec<_>>();
+    r.sort_by_key(|(i, _)| *i);
+    r.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Get the device and inode numbers of a file
+#[cfg(unix)]
+fn device_inode(file: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::metadata(file).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Get the device and inode numbers of a file (unavailable; keeps input order on one device)
+#[cfg(not(unix))]
+fn device_inode(file: &Path) -> Option<(u64, u64)> {
+    std::fs::metadata(file).ok().map(|_| (0, 0))
+}
+
+/**
+Process files with the given hash algorithm via Rayon parallel iterator, largest files first
+
+Files are stat'ed up front and started in order of decreasing size so that a single huge file
+does not end up being hashed alone after all the small files are done.
+Files of at least 64 MiB are additionally read in large blocks and hashed with
+[`Hasher::update_rayon`], which splits BLAKE3 across idle threads.
+Results are returned in input order.
+*/
+pub fn largest_first<P: AsRef<Path> + Clone + Send + Sync + 'static, O: Into<Options>>(
+    files: &[P],
+    options: O,
+) -> Vec<Result<String>> {
+    largest_first_by(files, &options.into())
+}
+
+/// Same as [`largest_first`] with any [`Process`]
+fn largest_first_by<P: AsRef<Path> + Clone + Send + Sync + 'static, T: Process>(
+    files: &[P],
+    process: &T,
+) -> Vec<Result<T::Output>> {
+    let mut order = files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| (std::fs::metadata(file.as_ref()).map_or(0, |m| m.len()), i))
+        .collect::<Vec<_>>();
+    order.sort_unstable_by_key(|&(size, i)| (std::cmp::Reverse(size), i));
+
+    let mut r = order
+        .into_par_iter()
+        .with_max_len(1)
+        .map(|(size, i)| {
+            let file = files[i].as_ref();
+            let result = if size >= LARGE_FILE_SIZE {
+                let options = process.options();
+                options
+                    .hashes(file, || hash_large_file(options.hash, file))
+                    .and_then(|hashes| process.finish(file, &hashes))
+            } else {
+                process.process(file)
+            };
+            (i, result)
+        })
+        .collect::<Vec<_>>();
+    r.sort_by_key(|(i, _)| *i);
+    r.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Hash a large file in large blocks using multiple threads
+fn hash_large_file(hash: Hash, file: &Path) -> Result<Vec<(String, String)>> {
+    let mut f = File::open(file)?;
+    let mut hasher = Hasher::new(hash);
+    let mut buffer = Vec::with_capacity(LARGE_BUFFER_SIZE);
+    loop {
+        buffer.clear();
+        let bytes_read = (&mut f)
+            .take(LARGE_BUFFER_SIZE as u64)
+            .read_to_end(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update_rayon(&buffer);
+    }
+    hasher.finalize(file)
+}
+
 /// Convert a finalized hash to a hex string
 fn to_hex_string(buffer: &[u8]) -> String {
     buffer.iter().fold(String::new(), |mut output, b| {
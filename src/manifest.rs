@@ -0,0 +1,391 @@
+use {
+    crate::{Algorithm, Hash, RELEASE_PREDICATE_TYPE, Status, in_toto, mtree, sidecar::list_file},
+    anyhow::{Result, anyhow, bail},
+    clap::ValueEnum,
+    rayon::prelude::*,
+    std::{
+        fmt::Write as _,
+        fs::{File, read_to_string},
+        io::Read,
+        path::{Path, PathBuf},
+    },
+};
+
+/// First line of a hashdeep manifest
+const HASHDEEP_HEADER: &str = "%%%% HASHDEEP-1.0";
+
+/// Size of the read buffer for CRC32
+const CRC32_BUFFER_SIZE: usize = 64 * 1024;
+
+/// CRC32 (IEEE 802.3, reflected) lookup table
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// Build the CRC32 lookup table
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0_u32;
+    while i < 256 {
+        let mut c = i;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 0 {
+                c >> 1
+            } else {
+                0xedb8_8320 ^ (c >> 1)
+            };
+            k += 1;
+        }
+        table[i as usize] = c;
+        i += 1;
+    }
+    table
+}
+
+/// Manifest formats that can be generated from a set of files
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Manifest {
+    /// `hashdeep` audit manifest (`size,<algorithms>,filename` rows)
+    Hashdeep,
+
+    /// BSD mtree spec (type, mode, uid, gid, size, SHA256/SHA512 digests)
+    Mtree,
+
+    /// in-toto Statement with a subject per file (name and digests)
+    InToto,
+}
+
+impl Manifest {
+    /**
+    Generate a manifest for the given files
+
+    # Errors
+
+    Returns an error if not able to read any of the files
+    */
+    pub fn generate<P: AsRef<Path> + Sync>(self, files: &[P], hash: Hash) -> Result<String> {
+        match self {
+            Manifest::Hashdeep => hashdeep(files, hash),
+            Manifest::Mtree => mtree(files, hash),
+            Manifest::InToto => in_toto(files, hash, RELEASE_PREDICATE_TYPE),
+        }
+    }
+}
+
+/**
+Calculate the CRC32 (as used by SFV files) of a file
+
+# Errors
+
+Returns an error if not able to read the given file
+*/
+pub fn file_crc32<P: AsRef<Path>>(file: P) -> Result<u32> {
+    let mut f = File::open(file)?;
+    let mut buffer = vec![0; CRC32_BUFFER_SIZE];
+    let mut crc = !0_u32;
+    loop {
+        let bytes_read = f.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        for &byte in &buffer[..bytes_read] {
+            crc = CRC32_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8);
+        }
+    }
+    Ok(!crc)
+}
+
+/**
+Verify the files listed in a Simple File Verification (`.sfv`) file
+
+Each line is a filename followed by its 8-digit hexadecimal CRC32; lines starting with `;` are
+comments.
+Filenames are resolved relative to the directory containing the SFV file; absolute filenames
+and filenames with `..` components are refused.
+Files are verified in parallel via Rayon and results are returned in file order.
+
+# Errors
+
+Returns an error if not able to read the SFV file or if it is malformed
+*/
+pub fn check_sfv<P: AsRef<Path>>(sfv: P) -> Result<Vec<Result<String>>> {
+    let sfv = sfv.as_ref();
+    check_sfv_content(sfv, &read_to_string(sfv)?)
+}
+
+/**
+Same as [`check_sfv`] with the content of the SFV file already read, e.g. after verifying its
+signature
+
+# Errors
+
+Returns an error if the SFV file is malformed
+*/
+pub(crate) fn check_sfv_content(sfv: &Path, content: &str) -> Result<Vec<Result<String>>> {
+    let entries = parse_sfv(content).map_err(|e| anyhow!("{}: {e}", sfv.display()))?;
+    let dir = sfv.parent().unwrap_or(Path::new(""));
+
+    Ok(entries
+        .par_iter()
+        .map(|(filename, expected)| {
+            let Some(file) = list_file(dir, filename) else {
+                bail!("{}: unsafe filename: {filename}", sfv.display());
+            };
+            let crc = file_crc32(&file).map_err(|e| anyhow!("{}: {e}", file.display()))?;
+            let status = if crc == *expected {
+                Status::Ok
+            } else {
+                Status::Failed
+            };
+            Ok(format!("{}: {status}", file.display()))
+        })
+        .collect())
+}
+
+/// Parse the content of an SFV file into `(filename, crc32)` pairs
+fn parse_sfv(content: &str) -> Result<Vec<(String, u32)>> {
+    let mut r = vec![];
+    for (i, line) in content.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let malformed = || anyhow!("malformed SFV at line {}: {line:?}", i + 1);
+        let (filename, crc) = line.rsplit_once([' ', '\t']).ok_or_else(malformed)?;
+        let filename = filename.trim_end();
+        if filename.is_empty() || crc.len() != 8 {
+            return Err(malformed());
+        }
+        let crc = u32::from_str_radix(crc, 16).map_err(|_| malformed())?;
+        r.push((filename.to_string(), crc));
+    }
+    if r.is_empty() {
+        bail!("malformed SFV: no files found");
+    }
+    Ok(r)
+}
+
+/// Row of a hashdeep manifest
+pub(crate) struct HashdeepEntry {
+    pub(crate) size: u64,
+    pub(crate) hashes: Vec<String>,
+    pub(crate) filename: String,
+}
+
+/// Check whether content is a hashdeep manifest
+pub(crate) fn is_hashdeep(content: &str) -> bool {
+    content
+        .trim_start_matches('\u{feff}')
+        .starts_with(HASHDEEP_HEADER)
+}
+
+/**
+Verify the files listed in a `hashdeep` manifest
+
+The size and every hash column fhc supports (`blake3`, `sha256`, `sha512`) are checked; other
+columns (`md5`, `sha1`, `tiger`, `whirlpool`) are ignored.
+Relative filenames are resolved against the `## Invoked from:` directory recorded by `hashdeep`
+(and [`hashdeep`]) if it exists, and otherwise relative to the directory containing the manifest;
+absolute filenames and filenames with `..` components are refused.
+Files are verified in parallel via Rayon and results are returned in manifest order.
+
+# Errors
+
+Returns an error if not able to read the manifest, if it is malformed, or if it has no supported
+hash column
+*/
+pub fn check_hashdeep<P: AsRef<Path>>(manifest: P) -> Result<Vec<Result<String>>> {
+    let manifest = manifest.as_ref();
+    check_hashdeep_content(manifest, &read_to_string(manifest)?)
+}
+
+/**
+Same as [`check_hashdeep`] with the content of the manifest already read, e.g. after verifying its
+signature
+
+# Errors
+
+Returns an error if the manifest is malformed or if it has no supported hash column
+*/
+pub(crate) fn check_hashdeep_content(
+    manifest: &Path,
+    content: &str,
+) -> Result<Vec<Result<String>>> {
+    let (algorithms, entries) =
+        parse_hashdeep(content).map_err(|e| anyhow!("{}: {e}", manifest.display()))?;
+    let hash = Hash::from_algorithms(&algorithms).ok_or_else(|| {
+        anyhow!(
+            "{}: no supported hash column in hashdeep manifest (supported: blake3, sha256, \
+             sha512)",
+            manifest.display()
+        )
+    })?;
+    let dir = invoked_from(content)
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| manifest.parent().unwrap_or(Path::new("")).to_path_buf());
+
+    Ok(entries
+        .par_iter()
+        .map(|entry| {
+            let Some(file) = list_file(&dir, &entry.filename) else {
+                bail!(
+                    "{}: unsafe filename: {}",
+                    manifest.display(),
+                    entry.filename
+                );
+            };
+            let size = file
+                .metadata()
+                .map_err(|e| anyhow!("{}: {e}", file.display()))?
+                .len();
+            let hashes = hash
+                .hash_file(&file)
+                .map_err(|e| anyhow!("{}: {e}", file.display()))?;
+            let status = if size == entry.size && hashes.iter().map(|x| &x.1).eq(&entry.hashes) {
+                Status::Ok
+            } else {
+                Status::Failed
+            };
+            Ok(format!("{}: {status}", file.display()))
+        })
+        .collect())
+}
+
+/// Get the directory a hashdeep manifest was generated in (its `## Invoked from:` comment)
+fn invoked_from(content: &str) -> Option<PathBuf> {
+    content
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .take_while(|line| line.starts_with("%%%%") || line.starts_with('#'))
+        .find_map(|line| line.trim_end().strip_prefix("## Invoked from: "))
+        .map(PathBuf::from)
+}
+
+/**
+Parse the content of a hashdeep manifest
+
+Returns the supported algorithms (in [`Hash::algorithms`] order) and the rows, with each row's
+hashes as `LABEL:digest` strings in the same order.
+*/
+pub(crate) fn parse_hashdeep(content: &str) -> Result<(Vec<Algorithm>, Vec<HashdeepEntry>)> {
+    let mut lines = content.trim_start_matches('\u{feff}').lines().enumerate();
+
+    if lines.next().map(|(_, line)| line.trim_end()) != Some(HASHDEEP_HEADER) {
+        bail!("malformed hashdeep manifest: missing {HASHDEEP_HEADER:?} header");
+    }
+
+    let columns = match lines.next() {
+        Some((_, line)) => line
+            .trim_end()
+            .strip_prefix("%%%% ")
+            .map(|columns| columns.split(',').collect::<Vec<_>>()),
+        None => None,
+    }
+    .filter(|columns| columns.len() >= 2 && columns[0] == "size")
+    .filter(|columns| columns.last() == Some(&"filename"))
+    .ok_or_else(|| anyhow!("malformed hashdeep manifest at line 2: invalid column header"))?;
+
+    // Supported hash columns as (column index, algorithm), in canonical order
+    let mut supported = columns
+        .iter()
+        .enumerate()
+        .filter_map(|(i, column)| match *column {
+            "blake3" => Some((i, Algorithm::Blake3)),
+            "sha256" => Some((i, Algorithm::Sha256)),
+            "sha512" => Some((i, Algorithm::Sha512)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    supported.sort_by_key(|x| x.1);
+
+    let mut entries = vec![];
+    for (i, line) in lines {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() || line.starts_with('#') || line.starts_with("%%%%") {
+            continue;
+        }
+        let malformed =
+            |reason: &str| anyhow!("malformed hashdeep manifest at line {}: {reason}", i + 1);
+        let fields = line.splitn(columns.len(), ',').collect::<Vec<_>>();
+        if fields.len() != columns.len() || fields[fields.len() - 1].is_empty() {
+            return Err(malformed("wrong number of columns"));
+        }
+        let size = fields[0]
+            .parse()
+            .map_err(|_| malformed(&format!("invalid size {:?}", fields[0])))?;
+        let hashes = supported
+            .iter()
+            .map(|&(j, algorithm)| {
+                let digest = fields[j].to_ascii_lowercase();
+                if digest.len() != algorithm.digest_len() * 2
+                    || !digest.bytes().all(|b| b.is_ascii_hexdigit())
+                {
+                    return Err(malformed(&format!(
+                        "invalid {} digest {:?}",
+                        algorithm.label(),
+                        fields[j]
+                    )));
+                }
+                Ok(format!("{}:{digest}", algorithm.label()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        entries.push(HashdeepEntry {
+            size,
+            hashes,
+            filename: fields[fields.len() - 1].to_string(),
+        });
+    }
+
+    Ok((supported.into_iter().map(|x| x.1).collect(), entries))
+}
+
+/**
+Generate a `hashdeep` manifest for the given files
+
+Rows contain each file's size and its hash for every algorithm of `hash` (e.g. [`Hash::All`]
+produces `size,blake3,sha256,sha512,filename`), in input order.
+Filenames are written as given, and the current directory is recorded as `## Invoked from:`, which
+[`check_hashdeep`] resolves relative filenames against (it refuses absolute filenames and
+filenames with `..` components).
+Files are hashed in parallel via Rayon.
+
+# Errors
+
+Returns an error if not able to read any of the files
+*/
+pub fn hashdeep<P: AsRef<Path> + Sync>(files: &[P], hash: Hash) -> Result<String> {
+    let rows = files
+        .par_iter()
+        .map(|file| {
+            let file = file.as_ref();
+            hashdeep_row(file, hash).map_err(|e| anyhow!("{}: {e}", file.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let columns = hash
+        .algorithms()
+        .iter()
+        .map(|algorithm| algorithm.label().to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut r = format!("{HASHDEEP_HEADER}\n%%%% size,{columns},filename\n");
+    if let Ok(dir) = std::env::current_dir() {
+        writeln!(r, "## Invoked from: {}", dir.display())?;
+    }
+    r.push_str("##\n");
+    for row in rows {
+        r.push_str(&row);
+        r.push('\n');
+    }
+    Ok(r)
+}
+
+/// Generate the hashdeep row for a file
+fn hashdeep_row(file: &Path, hash: Hash) -> Result<String> {
+    let mut r = file.metadata()?.len().to_string();
+    for (_, hash) in hash.hash_file(file)? {
+        r.push(',');
+        r.push_str(hash.split_once(':').map_or(hash.as_str(), |x| x.1));
+    }
+    write!(r, ",{}", file.display())?;
+    Ok(r)
+}
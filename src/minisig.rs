@@ -0,0 +1,96 @@
+use {
+    crate::Options,
+    anyhow::{Result, anyhow},
+    minisign::{PublicKey, SecretKey, SignatureBox},
+    std::{
+        fs::{File, write},
+        io::BufReader,
+        path::Path,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// Get the path of a file's minisign signature
+fn signature_path(file: &Path) -> String {
+    format!("{}.minisig", file.display())
+}
+
+/**
+Sign a hash file, manifest, or any other file with an Ed25519 (minisign) secret key
+
+The signature is written to an adjacent `.minisig` file in the format of the `minisign` tool
+(prehashed), with the timestamp and filename as its trusted comment, and its path is returned.
+
+# Errors
+
+Returns an error if the secret key is still encrypted or if not able to read the file or write
+the signature
+*/
+pub fn sign_minisign<P: AsRef<Path>>(file: P, secret_key: &SecretKey) -> Result<String> {
+    let file = file.as_ref();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let filename = file
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let signature = minisign::sign(
+        None,
+        secret_key,
+        BufReader::new(File::open(file)?),
+        Some(&format!("timestamp:{timestamp}\tfile:{filename}")),
+        Some("signature from fhc secret key"),
+    )?;
+    let path = signature_path(file);
+    write(&path, signature.to_string())?;
+    Ok(path)
+}
+
+/**
+Verify a file against its adjacent `.minisig` signature with a trusted public key
+
+Legacy (non-prehashed) signatures from older `minisign` versions are accepted.
+
+# Errors
+
+Returns an error if the signature is missing or malformed, was made with a different key, or does
+not match the file
+*/
+pub fn verify_minisign<P: AsRef<Path>>(file: P, public_key: &PublicKey) -> Result<()> {
+    let file = file.as_ref();
+    let path = signature_path(file);
+    if !Path::new(&path).exists() {
+        return Err(anyhow!("{}: unsigned (missing {path})", file.display()));
+    }
+    let signature = SignatureBox::from_file(&path).map_err(|e| anyhow!("{path}: {e}"))?;
+    minisign::verify(
+        public_key,
+        &signature,
+        BufReader::new(File::open(file)?),
+        true,
+        false,
+        true,
+    )
+    .map_err(|e| anyhow!("{}: bad signature ({e})", file.display()))
+}
+
+impl Options {
+    /// Verify existing hash file(s) with [`Options::trusted_key`], if any
+    pub(crate) fn verify_signatures(&self, hashes: &[(String, String)]) -> Result<()> {
+        if let Some(public_key) = &self.trusted_key {
+            for (ckfile, _) in hashes {
+                verify_minisign(ckfile, public_key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sign a new hash file with [`Options::signing_key`], if any
+    pub(crate) fn sign(&self, ckfile: &str) -> Result<()> {
+        if let Some(secret_key) = &self.signing_key {
+            sign_minisign(ckfile, secret_key)?;
+        }
+        Ok(())
+    }
+}
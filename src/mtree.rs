@@ -0,0 +1,465 @@
+use {
+    crate::{Algorithm, Hash, Status, sidecar::list_file},
+    anyhow::{Result, anyhow, bail},
+    rayon::prelude::*,
+    std::{
+        fmt::Write as _,
+        fs::{Metadata, read_link, read_to_string, symlink_metadata},
+        path::{Path, PathBuf},
+    },
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+/// First line of an mtree spec
+const MTREE_HEADER: &str = "#mtree";
+
+/// Entry of an mtree spec
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MtreeEntry {
+    /// Path relative to the spec's root, without a leading `./`
+    pub path: String,
+
+    /// File type (`file`, `dir`, `link`, `block`, `char`, `fifo`, `socket`)
+    pub kind: Option<String>,
+
+    /// Permission bits
+    pub mode: Option<u32>,
+
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub size: Option<u64>,
+
+    /// Symbolic link target
+    pub link: Option<String>,
+
+    /// Digests as `LABEL:digest` strings (SHA256 and/or SHA512)
+    pub digests: Vec<String>,
+
+    /// Line number
+    pub line: usize,
+}
+
+/**
+Generate an mtree spec for the given files
+
+Each line lists a file's type, mode, uid, gid (on Unix), size, and SHA256/SHA512 digests (BLAKE3
+has no mtree keyword and is skipped) or a symbolic link's target.
+Relative paths are prefixed with `./` and lines are in input order.
+The current directory is recorded in a `tree:` comment like `mtree -c` does, which
+[`check_mtree`] resolves relative paths against (it refuses absolute paths and paths with `..`
+components).
+Files are hashed in parallel via Rayon.
+
+# Errors
+
+Returns an error if `hash` does not include SHA256 or SHA512 or if not able to read any of the
+files
+*/
+pub fn mtree<P: AsRef<Path> + Sync>(files: &[P], hash: Hash) -> Result<String> {
+    let algorithms = hash
+        .algorithms()
+        .into_iter()
+        .filter(|algorithm| *algorithm != Algorithm::Blake3)
+        .collect::<Vec<_>>();
+    let hash = Hash::from_algorithms(&algorithms)
+        .ok_or_else(|| anyhow!("mtree supports only SHA256 and SHA512 digests"))?;
+
+    let rows = files
+        .par_iter()
+        .map(|file| {
+            let file = file.as_ref();
+            mtree_row(file, hash).map_err(|e| anyhow!("{}: {e}", file.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut r = format!("{MTREE_HEADER}\n");
+    if let Ok(dir) = std::env::current_dir() {
+        writeln!(r, "#\t   tree: {}", dir.display())?;
+    }
+    for row in rows {
+        r.push_str(&row);
+        r.push('\n');
+    }
+    Ok(r)
+}
+
+/// Generate the mtree line for a file
+fn mtree_row(file: &Path, hash: Hash) -> Result<String> {
+    let metadata = symlink_metadata(file)?;
+    let name = file.display().to_string();
+    let mut r = if name.starts_with('/') || name.starts_with("./") {
+        escape(&name)
+    } else {
+        format!("./{}", escape(&name))
+    };
+    let kind = file_kind(&metadata);
+    write!(r, " type={kind}")?;
+
+    #[cfg(unix)]
+    write!(
+        r,
+        " mode={:04o} uid={} gid={}",
+        metadata.mode() & 0o7777,
+        metadata.uid(),
+        metadata.gid()
+    )?;
+
+    if kind == "file" {
+        write!(r, " size={}", metadata.len())?;
+        for (_, hash) in hash.hash_file(file)? {
+            let (label, digest) = hash.split_once(':').unwrap_or_default();
+            write!(r, " {}digest={digest}", label.to_ascii_lowercase())?;
+        }
+    } else if kind == "link" {
+        write!(
+            r,
+            " link={}",
+            escape(&read_link(file)?.display().to_string())
+        )?;
+    }
+    Ok(r)
+}
+
+/// Get the mtree type of a file
+fn file_kind(metadata: &Metadata) -> &'static str {
+    let t = metadata.file_type();
+    if t.is_symlink() {
+        return "link";
+    }
+    if t.is_dir() {
+        return "dir";
+    }
+    #[cfg(unix)]
+    {
+        if t.is_block_device() {
+            return "block";
+        }
+        if t.is_char_device() {
+            return "char";
+        }
+        if t.is_fifo() {
+            return "fifo";
+        }
+        if t.is_socket() {
+            return "socket";
+        }
+    }
+    "file"
+}
+
+/// Encode a name with `vis`-style octal escapes for whitespace, `#`, `\`, and non-ASCII bytes
+fn escape(name: &str) -> String {
+    let mut r = String::new();
+    for b in name.bytes() {
+        if b.is_ascii_graphic() && b != b'#' && b != b'\\' {
+            r.push(char::from(b));
+        } else {
+            let _ = write!(r, "\\{b:03o}");
+        }
+    }
+    r
+}
+
+/// Decode `vis`-style octal and backslash escapes
+fn unescape(name: &str) -> Result<String> {
+    let bytes = name.as_bytes();
+    let mut r = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .filter(|x| x.iter().all(|b| (b'0'..=b'7').contains(b)));
+        match (bytes[i], octal) {
+            (b'\\', Some(octal)) => {
+                let n = octal.iter().fold(0_u32, |n, b| n * 8 + u32::from(b - b'0'));
+                r.push(u8::try_from(n)?);
+                i += 4;
+            }
+            (b'\\', None) if bytes.get(i + 1) == Some(&b'\\') => {
+                r.push(b'\\');
+                i += 2;
+            }
+            (b, _) => {
+                r.push(b);
+                i += 1;
+            }
+        }
+    }
+    Ok(String::from_utf8(r)?)
+}
+
+/**
+Parse the content of an mtree spec
+
+Supports `/set` and `/unset` defaults, line continuations, full paths (names containing `/`), and
+the hierarchical form where `type=dir` entries descend into the directory and `..` ascends.
+Keywords other than `type`, `mode`, `uid`, `gid`, `size`, `link`, `sha256digest`, and
+`sha512digest` (and their `sha256`/`sha512` aliases) are ignored.
+
+# Errors
+
+Returns an error if the spec is malformed
+*/
+pub fn parse_mtree(content: &str) -> Result<Vec<MtreeEntry>> {
+    let mut r = vec![];
+    let mut defaults: Vec<(String, String)> = vec![];
+    let mut cwd: Vec<String> = vec![];
+    let mut pending = String::new();
+    let mut start = 0;
+
+    for (i, line) in content.trim_start_matches('\u{feff}').lines().enumerate() {
+        if pending.is_empty() {
+            start = i + 1;
+        }
+        let line = line.trim_end_matches('\r');
+        if let Some(line) = line.strip_suffix('\\') {
+            pending.push_str(line);
+            pending.push(' ');
+            continue;
+        }
+        pending.push_str(line);
+        let line = std::mem::take(&mut pending);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut tokens = line.split_whitespace();
+        let name = tokens.next().unwrap_or_default();
+        let keywords = tokens
+            .map(|token| {
+                let (key, value) = token.split_once('=').unwrap_or((token, ""));
+                (key.to_string(), value.to_string())
+            })
+            .collect::<Vec<_>>();
+
+        match name {
+            "/set" => {
+                for (key, value) in keywords {
+                    defaults.retain(|x| x.0 != key);
+                    defaults.push((key, value));
+                }
+            }
+            "/unset" => {
+                for (key, _) in keywords {
+                    if key == "all" {
+                        defaults.clear();
+                    }
+                    defaults.retain(|x| x.0 != key);
+                }
+            }
+            ".." => {
+                if cwd.pop().is_none() {
+                    bail!("malformed mtree at line {start}: \"..\" above the root");
+                }
+            }
+            _ => {
+                let name = unescape(name)
+                    .map_err(|e| anyhow!("malformed mtree at line {start}: {name:?}: {e}"))?;
+                let mut entry = MtreeEntry {
+                    line: start,
+                    ..MtreeEntry::default()
+                };
+                for (key, value) in defaults.iter().chain(&keywords) {
+                    set_keyword(&mut entry, key, value)
+                        .map_err(|e| anyhow!("malformed mtree at line {start}: {e}"))?;
+                }
+                if name.contains('/') {
+                    entry.path = normalize(&name);
+                } else {
+                    let mut path = cwd.clone();
+                    path.push(name.clone());
+                    entry.path = normalize(&path.join("/"));
+                    if entry.kind.as_deref() == Some("dir") && name != "." {
+                        cwd.push(name);
+                    }
+                }
+                r.push(entry);
+            }
+        }
+    }
+
+    if r.is_empty() {
+        bail!("malformed mtree: no entries found");
+    }
+
+    Ok(r)
+}
+
+/// Strip `.` components from a path
+fn normalize(path: &str) -> String {
+    let r = path
+        .split('/')
+        .enumerate()
+        .filter(|(i, x)| (*i == 0 || !x.is_empty()) && *x != ".")
+        .map(|x| x.1)
+        .collect::<Vec<_>>()
+        .join("/");
+    if r.is_empty() { String::from(".") } else { r }
+}
+
+/// Apply a keyword to an entry
+fn set_keyword(entry: &mut MtreeEntry, key: &str, value: &str) -> Result<()> {
+    let invalid = || anyhow!("invalid {key} {value:?}");
+    match key {
+        "type" => entry.kind = Some(value.to_string()),
+        "mode" => entry.mode = Some(u32::from_str_radix(value, 8).map_err(|_| invalid())?),
+        "uid" => entry.uid = Some(value.parse().map_err(|_| invalid())?),
+        "gid" => entry.gid = Some(value.parse().map_err(|_| invalid())?),
+        "size" => entry.size = Some(value.parse().map_err(|_| invalid())?),
+        "link" => entry.link = Some(unescape(value)?),
+        "sha256digest" | "sha256" | "sha512digest" | "sha512" => {
+            let algorithm = if key.starts_with("sha256") {
+                Algorithm::Sha256
+            } else {
+                Algorithm::Sha512
+            };
+            let digest = value.to_ascii_lowercase();
+            if digest.len() != algorithm.digest_len() * 2
+                || !digest.bytes().all(|b| b.is_ascii_hexdigit())
+            {
+                return Err(invalid());
+            }
+            let hash = format!("{}:{digest}", algorithm.label());
+            entry.digests.retain(|x| !x.starts_with(algorithm.label()));
+            entry.digests.push(hash);
+            entry.digests.sort();
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Check whether content is an mtree spec
+pub(crate) fn is_mtree(content: &str) -> bool {
+    content
+        .trim_start_matches('\u{feff}')
+        .starts_with(MTREE_HEADER)
+}
+
+/**
+Verify the files listed in an mtree spec
+
+Relative paths are resolved against the directory of the spec's `tree:` comment (as written by
+[`mtree`] and `mtree -c`) if it exists, and otherwise relative to the directory containing the
+spec; absolute paths and paths with `..` components are refused.
+Content drift (size or digest) is reported as `FAILED` and metadata drift (type, mode, uid, gid,
+or link target) as `METADATA CHANGED` followed by the changed keywords, e.g.
+`./bin/sh: FAILED, METADATA CHANGED (mode, uid)`.
+Files are verified in parallel via Rayon and results are returned in spec order.
+
+# Errors
+
+Returns an error if not able to read the spec or if it is malformed
+*/
+pub fn check_mtree<P: AsRef<Path>>(spec: P) -> Result<Vec<Result<String>>> {
+    let spec = spec.as_ref();
+    check_mtree_content(spec, &read_to_string(spec)?)
+}
+
+/**
+Same as [`check_mtree`] with the content of the spec already read, e.g. after verifying its
+signature
+
+# Errors
+
+Returns an error if the spec is malformed
+*/
+pub(crate) fn check_mtree_content(spec: &Path, content: &str) -> Result<Vec<Result<String>>> {
+    let entries = parse_mtree(content).map_err(|e| anyhow!("{}: {e}", spec.display()))?;
+    let dir = tree(content)
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| spec.parent().unwrap_or(Path::new("")).to_path_buf());
+
+    Ok(entries
+        .par_iter()
+        .map(|entry| {
+            let Some(file) = list_file(&dir, &entry.path) else {
+                bail!(
+                    "{}: unsafe path at line {}: {}",
+                    spec.display(),
+                    entry.line,
+                    entry.path
+                );
+            };
+            check_mtree_entry(entry, &file).map_err(|e| anyhow!("{}: {e}", file.display()))
+        })
+        .collect())
+}
+
+/// Get the root directory of an mtree spec from its leading `tree:` comment
+fn tree(content: &str) -> Option<PathBuf> {
+    content
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .take_while(|line| line.starts_with('#'))
+        .find_map(|line| line[1..].trim_start().strip_prefix("tree:"))
+        .map(|dir| PathBuf::from(dir.trim()))
+}
+
+/// Verify a single mtree entry against a file
+fn check_mtree_entry(entry: &MtreeEntry, file: &Path) -> Result<String> {
+    let metadata = symlink_metadata(file)?;
+    let kind = file_kind(&metadata);
+    let mut changed = vec![];
+    let mut status = Status::Ok;
+
+    if entry.kind.as_deref().is_some_and(|x| x != kind) {
+        changed.push("type");
+    } else {
+        #[cfg(unix)]
+        {
+            if entry.mode.is_some_and(|x| x != metadata.mode() & 0o7777) {
+                changed.push("mode");
+            }
+            if entry.uid.is_some_and(|x| x != metadata.uid()) {
+                changed.push("uid");
+            }
+            if entry.gid.is_some_and(|x| x != metadata.gid()) {
+                changed.push("gid");
+            }
+        }
+        if kind == "link"
+            && let Some(link) = &entry.link
+            && read_link(file)?.to_str() != Some(link.as_str())
+        {
+            changed.push("link");
+        }
+        if kind == "file" {
+            if entry.size.is_some_and(|x| x != metadata.len()) {
+                status = Status::Failed;
+            } else if let Some(hash) = Hash::from_algorithms(
+                &entry
+                    .digests
+                    .iter()
+                    .filter_map(|x| Algorithm::from_label(x.split(':').next()?))
+                    .collect::<Vec<_>>(),
+            ) && !hash
+                .hash_file(file)?
+                .iter()
+                .map(|x| &x.1)
+                .eq(&entry.digests)
+            {
+                status = Status::Failed;
+            }
+        }
+    }
+
+    Ok(match (status, changed.is_empty()) {
+        (status, true) => format!("{}: {status}", file.display()),
+        (Status::Ok, false) => {
+            format!(
+                "{}: METADATA CHANGED ({})",
+                file.display(),
+                changed.join(", ")
+            )
+        }
+        (status, false) => format!(
+            "{}: {status}, METADATA CHANGED ({})",
+            file.display(),
+            changed.join(", ")
+        ),
+    })
+}
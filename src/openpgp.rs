@@ -0,0 +1,196 @@
+use {
+    crate::sidecar::check_list_content,
+    anyhow::{Result, anyhow, bail},
+    pgp::{
+        composed::{
+            CleartextSignedMessage, Deserializable, DetachedSignature, SignedPublicKey,
+            SignedPublicSubKey,
+        },
+        packet::{Signature, SignatureType},
+        types::{KeyDetails, Timestamp},
+    },
+    std::{
+        fs::{File, read},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Extensions of detached signatures, in the order they are looked for
+const SIGNATURE_EXTENSIONS: [&str; 4] = ["gpg", "sig", "sign", "asc"];
+
+/// Header of a clearsigned message
+const CLEARSIGNED_HEADER: &str = "-----BEGIN PGP SIGNED MESSAGE-----";
+
+/// Header of an SSH signature, e.g. a `.sig` file written by `ssh-keygen -Y sign`
+const SSH_SIGNATURE_HEADER: &str = "-----BEGIN SSH SIGNATURE-----";
+
+/**
+Load the public keys of an OpenPGP keyring file (binary or ASCII armored)
+
+Keys whose self-signatures or subkey bindings do not verify are skipped.
+
+# Errors
+
+Returns an error if not able to read the keyring or if it has no valid keys
+*/
+pub fn load_keyring<P: AsRef<Path>>(keyring: P) -> Result<Vec<SignedPublicKey>> {
+    let keyring = keyring.as_ref();
+    let (keys, _) = SignedPublicKey::from_reader_many(File::open(keyring)?)
+        .map_err(|e| anyhow!("{}: {e}", keyring.display()))?;
+    let keys = keys
+        .filter_map(Result::ok)
+        .filter(|key| key.verify_bindings().is_ok())
+        .collect::<Vec<_>>();
+    if keys.is_empty() {
+        bail!("{}: no valid public keys", keyring.display());
+    }
+    Ok(keys)
+}
+
+/**
+Verify the OpenPGP signature of a checksum list and return the signed content
+
+A clearsigned list (e.g. `SHA256SUMS.asc`) is verified in place; otherwise a detached signature
+(binary or ASCII armored) is looked for next to it with a `.gpg`, `.sig`, `.sign`, or `.asc`
+extension (e.g. `SHA256SUMS.gpg`).
+The signature must be made by an unrevoked primary key or by a subkey of one of the given keys
+that is bound for signing and was neither revoked nor expired when it signed; nothing is fetched
+from a keyserver.
+
+# Errors
+
+Returns an error if not able to read the list or its signature, if there is no signature, or if
+no signature verifies with the given keys
+*/
+pub fn verify_openpgp<P: AsRef<Path>>(list: P, keys: &[SignedPublicKey]) -> Result<String> {
+    let list = list.as_ref();
+    let content = read(list)?;
+
+    if content.starts_with(CLEARSIGNED_HEADER.as_bytes()) {
+        let (message, _) = CleartextSignedMessage::from_armor(content.as_slice())
+            .map_err(|e| anyhow!("{}: {e}", list.display()))?;
+        let text = message.signed_text();
+        if message
+            .signatures()
+            .iter()
+            .any(|signature| signed_by(keys, signature, text.as_bytes()))
+        {
+            return Ok(text.replace("\r\n", "\n"));
+        }
+        bail!("{}: bad signature", list.display());
+    }
+
+    let Some(signature_path) = signature_path(list) else {
+        bail!(
+            "{}: unsigned (no clearsigned content or detached signature)",
+            list.display()
+        );
+    };
+    let (signature, _) = DetachedSignature::from_reader_single(File::open(&signature_path)?)
+        .map_err(|e| anyhow!("{}: {e}", signature_path.display()))?;
+    if signed_by(keys, &signature.signature, &content) {
+        return Ok(String::from_utf8(content)?);
+    }
+    bail!(
+        "{}: bad signature ({})",
+        list.display(),
+        signature_path.display()
+    );
+}
+
+/**
+Verify the OpenPGP signature of a checksum list and then the files it lists
+
+Same as [`check_list`](crate::check_list), but only after the list's signature is verified with
+[`verify_openpgp`]; the signed content is what gets verified.
+
+# Errors
+
+Returns an error if the signature does not verify, or if the list is malformed
+*/
+pub fn check_list_openpgp<P: AsRef<Path>>(
+    list: P,
+    keys: &[SignedPublicKey],
+) -> Result<Vec<Result<String>>> {
+    let list = list.as_ref();
+    let content = verify_openpgp(list, keys)?;
+    check_list_content(list, &content)
+}
+
+/**
+Find the detached signature of a file
+
+Clearsigned copies of it (e.g. `SHA256SUMS.asc`) and SSH signatures (e.g. `SHA256SUMS.sig`) are
+skipped.
+*/
+fn signature_path(file: &Path) -> Option<PathBuf> {
+    SIGNATURE_EXTENSIONS.into_iter().find_map(|extension| {
+        let mut path = file.as_os_str().to_owned();
+        path.push(".");
+        path.push(extension);
+        let path = PathBuf::from(path);
+        read(&path)
+            .is_ok_and(|content| {
+                ![CLEARSIGNED_HEADER, SSH_SIGNATURE_HEADER]
+                    .iter()
+                    .any(|header| content.starts_with(header.as_bytes()))
+            })
+            .then_some(path)
+    })
+}
+
+/// Check whether a signature was made by the primary key or a signing subkey of any of the keys
+fn signed_by(keys: &[SignedPublicKey], signature: &Signature, data: &[u8]) -> bool {
+    let created = signature.created().unwrap_or_else(Timestamp::now).as_secs();
+    keys.iter().any(|key| {
+        !revoked(key)
+            && (signature.verify(key, data).is_ok()
+                || key.public_subkeys.iter().any(|subkey| {
+                    can_sign(key, subkey, created) && signature.verify(subkey, data).is_ok()
+                }))
+    })
+}
+
+/// Check whether the primary key of a key is revoked
+fn revoked(key: &SignedPublicKey) -> bool {
+    key.details
+        .revocation_signatures
+        .iter()
+        .any(|signature| signature.verify_key(&key.primary_key).is_ok())
+}
+
+/**
+Check whether a subkey could sign at the given time (seconds since the Unix epoch)
+
+The subkey must not be revoked and its latest binding signature must have the signing flag and
+no key expiration time before `created`.
+*/
+fn can_sign(key: &SignedPublicKey, subkey: &SignedPublicSubKey, created: u32) -> bool {
+    let mut binding: Option<&Signature> = None;
+    for signature in &subkey.signatures {
+        if signature
+            .verify_subkey_binding(&key.primary_key, &subkey.key)
+            .is_err()
+        {
+            continue;
+        }
+        match signature.typ() {
+            Some(SignatureType::SubkeyRevocation) => return false,
+            Some(SignatureType::SubkeyBinding)
+                if binding.is_none_or(|binding| binding.created() < signature.created()) =>
+            {
+                binding = Some(signature);
+            }
+            _ => {}
+        }
+    }
+    binding.is_some_and(|binding| {
+        binding.key_flags().sign()
+            && binding.key_expiration_time().is_none_or(|expiration| {
+                expiration.as_secs() == 0
+                    || u64::from(created)
+                        < u64::from(subkey.key.created_at().as_secs())
+                            + u64::from(expiration.as_secs())
+            })
+    })
+}
@@ -0,0 +1,232 @@
+use {
+    crate::{Algorithm, Options, Status, encoding::split_hash},
+    anyhow::{Result, anyhow, bail},
+    bao::{decode::SliceDecoder, encode::Encoder, encode::SliceExtractor},
+    std::{
+        ffi::OsString,
+        fs::{File, OpenOptions},
+        io::{ErrorKind, Read, Write, copy},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Get the path of a file's Bao outboard encoding
+fn outboard_path(file: &Path) -> String {
+    format!("{}.obao", file.display())
+}
+
+/// Parse a labeled BLAKE3 hash (`BLAKE3:hex`) as produced by [`file_blake3`](crate::file_blake3)
+fn root_hash(hash: &str) -> Result<blake3::Hash> {
+    let (algorithm, digest) = split_hash(hash)?;
+    if algorithm != Algorithm::Blake3 {
+        bail!("Bao requires a BLAKE3 hash but found {}", algorithm.label());
+    }
+    let digest: [u8; 32] = digest
+        .try_into()
+        .map_err(|_| anyhow!("invalid BLAKE3 digest in {hash:?}"))?;
+    Ok(blake3::Hash::from_bytes(digest))
+}
+
+/**
+Write the Bao outboard encoding of a file to an adjacent `.obao` file
+
+The outboard holds the BLAKE3 hash tree of the file (without its content), so any slice of the
+file can later be verified against the root hash alone.
+Returns the root hash as `BLAKE3:hex`, which equals the hash from
+[`file_blake3`](crate::file_blake3).
+
+# Errors
+
+Returns an error if not able to read the file or write the outboard
+*/
+pub fn write_outboard<P: AsRef<Path>>(file: P) -> Result<String> {
+    let file = file.as_ref();
+    let outboard = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(outboard_path(file))?;
+    let mut encoder = Encoder::new_outboard(outboard);
+    copy(&mut File::open(file)?, &mut encoder)?;
+    Ok(format!("BLAKE3:{}", encoder.finalize()?))
+}
+
+/// Bao outboard encoding of a file, written to a temporary file until it is kept
+pub(crate) struct Outboard {
+    encoder: Option<Encoder<File>>,
+    path: PathBuf,
+}
+
+impl Outboard {
+    /// Start encoding the outboard of a file in a temporary dot file next to it
+    fn create(file: &Path) -> Result<Outboard> {
+        let mut name = OsString::from(".");
+        name.push(
+            file.file_name()
+                .ok_or_else(|| anyhow!("{}: not a file", file.display()))?,
+        );
+        name.push(".obao.tmp");
+        let path = file.with_file_name(name);
+        let outboard = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        Ok(Outboard {
+            encoder: Some(Encoder::new_outboard(outboard)),
+            path,
+        })
+    }
+
+    /// Finish the outboard and replace the file's `.obao` file with it
+    fn keep(mut self, file: &Path) -> Result<()> {
+        if let Some(mut encoder) = self.encoder.take() {
+            encoder.finalize()?;
+            drop(encoder.into_inner());
+            std::fs::rename(&self.path, outboard_path(file))?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for Outboard {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.encoder {
+            Some(encoder) => encoder.write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.encoder {
+            Some(encoder) => encoder.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Outboard {
+    fn drop(&mut self) {
+        if self.encoder.take().is_some() {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl Options {
+    /**
+    Start the outboard of a file to write in the same read as its hash(es) (see [`Options::bao`])
+
+    # Errors
+
+    Returns an error if [`Options::hash`] does not include BLAKE3 or if not able to create the
+    outboard
+    */
+    pub(crate) fn outboard(&self, file: &Path) -> Result<Option<Outboard>> {
+        if !self.bao || self.dry_run {
+            return Ok(None);
+        }
+        self.check_bao()?;
+        Outboard::create(file).map(Some)
+    }
+
+    /// Refuse [`Options::bao`] unless [`Options::hash`] includes BLAKE3
+    fn check_bao(&self) -> Result<()> {
+        if !self.hash.algorithms().contains(&Algorithm::Blake3) {
+            bail!("Bao requires a BLAKE3 hash");
+        }
+        Ok(())
+    }
+
+    /**
+    Keep the outboard of a file if its hash file(s) were just saved or it verified OK
+
+    Without an outboard from the same read (e.g. digests from the cache), a missing outboard is
+    written with [`write_outboard`].
+
+    # Errors
+
+    Returns an error if not able to read the hash file(s), read the file, or write the outboard
+    */
+    pub(crate) fn finish_outboard(
+        &self,
+        file: &Path,
+        hashes: &[(String, String)],
+        saved: bool,
+        outboard: Option<Outboard>,
+    ) -> Result<()> {
+        if !self.bao || self.dry_run || !(saved || self.status(file, hashes)?.0 == Status::Ok) {
+            return Ok(());
+        }
+        self.check_bao()?;
+        match outboard {
+            Some(outboard) => outboard.keep(file),
+            None if !Path::new(&outboard_path(file)).exists() => write_outboard(file).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+}
+
+/**
+Extract a verifiable Bao slice of a file using its `.obao` outboard
+
+The slice contains the requested content plus the hash tree nodes needed to verify it, ready to
+send to a client that checks it with [`decode_slice`].
+
+# Errors
+
+Returns an error if not able to read the file or its outboard
+*/
+pub fn extract_slice<P: AsRef<Path>>(file: P, start: u64, len: u64) -> Result<Vec<u8>> {
+    let file = file.as_ref();
+    let mut extractor = SliceExtractor::new_outboard(
+        File::open(file)?,
+        File::open(outboard_path(file))?,
+        start,
+        len,
+    );
+    let mut r = vec![];
+    extractor.read_to_end(&mut r)?;
+    Ok(r)
+}
+
+/**
+Verify a Bao slice against a root BLAKE3 hash (`BLAKE3:hex`) and return its content
+
+# Errors
+
+Returns an error if the hash is not a BLAKE3 hash or if the slice does not match it
+*/
+pub fn decode_slice(slice: &[u8], hash: &str, start: u64, len: u64) -> Result<Vec<u8>> {
+    let mut decoder = SliceDecoder::new(slice, &root_hash(hash)?, start, len);
+    let mut r = vec![];
+    decoder.read_to_end(&mut r)?;
+    Ok(r)
+}
+
+/**
+Verify a byte range of a file against a root BLAKE3 hash (`BLAKE3:hex`)
+
+Only the requested range (rounded to 1 KiB chunks) and the hash tree nodes along its path are
+read from the file and its `.obao` outboard (see [`write_outboard`]).
+
+# Errors
+
+Returns an error if the hash is not a BLAKE3 hash or if not able to read the file or its
+outboard
+*/
+pub fn verify_slice<P: AsRef<Path>>(file: P, hash: &str, start: u64, len: u64) -> Result<Status> {
+    let slice = extract_slice(file, start, len)?;
+    match decode_slice(&slice, hash, start, len) {
+        Ok(_) => Ok(Status::Ok),
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == ErrorKind::InvalidData) =>
+        {
+            Ok(Status::Failed)
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,597 @@
+use {
+    crate::{
+        Hash, Status,
+        chunks::{Chunks, corruption, parse_chunk_comment},
+        decode_digest,
+        encoding::parse_sri_token,
+        in_toto::{check_in_toto_content, is_in_toto},
+        manifest::{check_hashdeep_content, check_sfv_content, is_hashdeep},
+        mtree::{check_mtree_content, is_mtree},
+        stat, to_hex_string,
+    },
+    anyhow::{Result, anyhow, bail},
+    rayon::prelude::*,
+    std::{
+        fs::read_to_string,
+        path::{Component, Path, PathBuf},
+    },
+};
+
+/// Single hash algorithm
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Algorithm {
+    Blake3,
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    /// Label prefixed to hashes (`BLAKE3`, `SHA256`, `SHA512`)
+    #[must_use]
+    pub fn label(self) -> &'static str {
+        match self {
+            Algorithm::Blake3 => "BLAKE3",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha512 => "SHA512",
+        }
+    }
+
+    /// Extension of hash files (`b3`, `sha256`, `sha512`)
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Algorithm::Blake3 => "b3",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+        }
+    }
+
+    /// Digest length in bytes
+    #[must_use]
+    pub fn digest_len(self) -> usize {
+        match self {
+            Algorithm::Blake3 | Algorithm::Sha256 => 32,
+            Algorithm::Sha512 => 64,
+        }
+    }
+
+    /**
+    Guess the algorithm of a checksum list from its name
+
+    Recognizes hash file extensions (`.b3`, `.sha256`, `.sha512`) and conventional list names
+    (`B3SUMS`, `SHA256SUMS`, `SHA512SUMS`).
+    */
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Algorithm> {
+        let algorithms = [Algorithm::Blake3, Algorithm::Sha256, Algorithm::Sha512];
+        let extension = path.extension().and_then(|x| x.to_str()).unwrap_or("");
+        let name = path.file_stem().and_then(|x| x.to_str()).unwrap_or("");
+        algorithms
+            .into_iter()
+            .find(|algorithm| algorithm.extension().eq_ignore_ascii_case(extension))
+            .or_else(|| {
+                algorithms.into_iter().find(|algorithm| {
+                    let name = name.to_ascii_uppercase();
+                    name.starts_with(algorithm.label())
+                        || name.starts_with(&algorithm.extension().to_ascii_uppercase())
+                })
+            })
+    }
+
+    /// Get the algorithm for a label (case-insensitive; also accepts `SHA2-256` and `SHA2-512`)
+    #[must_use]
+    pub fn from_label(label: &str) -> Option<Algorithm> {
+        let label = label.to_ascii_uppercase();
+        let label = match label.as_str() {
+            "SHA2-256" => "SHA256",
+            "SHA2-512" => "SHA512",
+            label => label,
+        };
+        [Algorithm::Blake3, Algorithm::Sha256, Algorithm::Sha512]
+            .into_iter()
+            .find(|algorithm| algorithm.label() == label)
+    }
+}
+
+impl From<Algorithm> for Hash {
+    fn from(algorithm: Algorithm) -> Hash {
+        match algorithm {
+            Algorithm::Blake3 => Hash::Blake3,
+            Algorithm::Sha256 => Hash::Sha256,
+            Algorithm::Sha512 => Hash::Sha512,
+        }
+    }
+}
+
+impl Hash {
+    /// Get the individual algorithm(s) in the order their hashes are produced
+    #[must_use]
+    pub fn algorithms(self) -> Vec<Algorithm> {
+        match self {
+            Hash::Blake3 => vec![Algorithm::Blake3],
+            Hash::Sha256 => vec![Algorithm::Sha256],
+            Hash::Sha512 => vec![Algorithm::Sha512],
+            Hash::Blake3Sha256 => vec![Algorithm::Blake3, Algorithm::Sha256],
+            Hash::Blake3Sha512 => vec![Algorithm::Blake3, Algorithm::Sha512],
+            Hash::Sha256Sha512 => vec![Algorithm::Sha256, Algorithm::Sha512],
+            Hash::All => vec![Algorithm::Blake3, Algorithm::Sha256, Algorithm::Sha512],
+        }
+    }
+
+    /// Get the hash that produces exactly the given algorithm(s), if any
+    #[must_use]
+    pub fn from_algorithms(algorithms: &[Algorithm]) -> Option<Hash> {
+        let mut algorithms = algorithms.to_vec();
+        algorithms.sort();
+        algorithms.dedup();
+        [
+            Hash::Blake3,
+            Hash::Sha256,
+            Hash::Sha512,
+            Hash::Blake3Sha256,
+            Hash::Blake3Sha512,
+            Hash::Sha256Sha512,
+            Hash::All,
+        ]
+        .into_iter()
+        .find(|hash| hash.algorithms() == algorithms)
+    }
+}
+
+/// Hash file line format
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// `LABEL:digest  filename` (`sha256sum` style, with algorithm label)
+    #[default]
+    Gnu,
+
+    /// `LABEL (filename) = digest` (BSD `--tag` style)
+    Bsd,
+}
+
+impl Format {
+    /**
+    Format a hash file line for an encoded hash (see [`Encoding::encode`](crate::Encoding::encode)) and filename
+
+    Filenames containing a backslash or newline are escaped and the line is prefixed with `\`.
+
+    # Errors
+
+    Returns an error if the hash is not labeled (`LABEL:digest`) for the BSD format
+    */
+    pub fn line(self, hash: &str, filename: &str) -> Result<String> {
+        let (prefix, filename) = escape(filename);
+        Ok(match self {
+            Format::Gnu => format!("{prefix}{hash}  {filename}\n"),
+            Format::Bsd => {
+                let Some((label, digest)) = hash.split_once(':') else {
+                    bail!("BSD format requires a labeled hash but found {hash:?}");
+                };
+                format!("{prefix}{label} ({filename}) = {digest}\n")
+            }
+        })
+    }
+}
+
+/// Hash entry parsed from a hash file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// Hash algorithm
+    pub algorithm: Algorithm,
+
+    /// Lowercase hex digest
+    pub digest: String,
+
+    /// Recorded filename, if any
+    pub filename: Option<String>,
+
+    /// Recorded with the `*` binary mode marker
+    pub binary: bool,
+
+    /// Line number (1-based)
+    pub line: usize,
+
+    /// Chunk digests recorded in `# chunks` comments following the line, if any
+    pub chunks: Option<Chunks>,
+
+    /// Size recorded in a `# stat` comment following the line, if any
+    pub size: Option<u64>,
+
+    /// Modification time (nanoseconds since the Unix epoch) recorded in a `# stat` comment
+    /// following the line, if any
+    pub mtime: Option<i128>,
+}
+
+impl Entry {
+    /// Labeled hash as produced by [`Hash::hash_file`] (`LABEL:hex`)
+    #[must_use]
+    pub fn hash(&self) -> String {
+        format!("{}:{}", self.algorithm.label(), self.digest)
+    }
+
+    /**
+    Check whether the entry is for the given file
+
+    The recorded filename (ignoring `./` components) must equal the file's name; entries without
+    a filename match any file.
+    */
+    #[must_use]
+    pub fn matches(&self, file: &Path) -> bool {
+        let Some(filename) = &self.filename else {
+            return true;
+        };
+        let recorded = Path::new(filename)
+            .components()
+            .filter(|c| *c != Component::CurDir)
+            .collect::<Vec<_>>();
+        match (recorded.as_slice(), file.file_name()) {
+            ([Component::Normal(recorded)], Some(name)) => *recorded == name,
+            _ => false,
+        }
+    }
+
+    /**
+    Classify a failed verification of the given file by the recorded size and modification time
+
+    Returns [`Status::Modified`] if either changed (the file was most likely edited),
+    [`Status::Corrupted`] if both are unchanged although the content changed (e.g. bit rot), or
+    [`Status::Failed`] if none were recorded or the file's metadata cannot be read.
+    */
+    #[must_use]
+    pub fn failure(&self, file: &Path) -> Status {
+        let (Some(size), Some(mtime)) = (self.size, self.mtime) else {
+            return Status::Failed;
+        };
+        match stat(file) {
+            Ok(stat) if stat == (size, mtime) => Status::Corrupted,
+            Ok(_) => Status::Modified,
+            Err(_) => Status::Failed,
+        }
+    }
+}
+
+/**
+Get the `# stat <size> <mtime>` comment line recording a file's size and modification time
+(nanoseconds since the Unix epoch)
+
+# Errors
+
+Returns an error if not able to read the file's metadata
+*/
+pub(crate) fn stat_comment(file: &Path) -> Result<String> {
+    let (size, mtime) = stat(file)?;
+    Ok(format!("# stat {size} {mtime}\n"))
+}
+
+/**
+Verify the files listed in a checksum list
+
+Each entry's filename is resolved relative to the directory containing the list and the file is
+hashed with the entry's algorithm; absolute filenames and filenames with `..` components are
+refused.
+Unlabeled entries use the algorithm guessed from the list's name (see [`Algorithm::from_path`]).
+SFV files (`.sfv` extension), hashdeep manifests (`%%%% HASHDEEP-1.0` header), mtree specs
+(`#mtree` header), and in-toto attestations (JSON) are verified via
+[`check_sfv`](crate::check_sfv), [`check_hashdeep`](crate::check_hashdeep),
+[`check_mtree`](crate::check_mtree), and [`check_in_toto`](crate::check_in_toto).
+Files are verified in parallel via Rayon and results are returned in list order.
+
+# Errors
+
+Returns an error if not able to read the list or if the list is malformed
+*/
+pub fn check_list<P: AsRef<Path>>(list: P) -> Result<Vec<Result<String>>> {
+    let list = list.as_ref();
+    check_list_content(list, &read_to_string(list)?)
+}
+
+/**
+Same as [`check_list`] with the content of the list already read, e.g. after verifying its
+signature
+
+# Errors
+
+Returns an error if the list is malformed
+*/
+pub(crate) fn check_list_content(list: &Path, content: &str) -> Result<Vec<Result<String>>> {
+    if list
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("sfv"))
+    {
+        return check_sfv_content(list, content);
+    }
+    if is_hashdeep(content) {
+        return check_hashdeep_content(list, content);
+    }
+    if is_mtree(content) {
+        return check_mtree_content(list, content);
+    }
+    if is_in_toto(content) {
+        return check_in_toto_content(list, content);
+    }
+    check_entries(list, content)
+}
+
+/**
+Verify the files listed in the content of a checksum list
+
+# Errors
+
+Returns an error if the list is malformed
+*/
+fn check_entries(list: &Path, content: &str) -> Result<Vec<Result<String>>> {
+    let entries = parse_sidecar(content, Algorithm::from_path(list))
+        .map_err(|e| anyhow!("{}: {e}", list.display()))?;
+    let dir = list.parent().unwrap_or(Path::new(""));
+
+    Ok(entries
+        .par_iter()
+        .map(|entry| {
+            let Some(filename) = &entry.filename else {
+                bail!(
+                    "{}: malformed sidecar at line {}: missing filename",
+                    list.display(),
+                    entry.line
+                );
+            };
+            let Some(file) = list_file(dir, filename) else {
+                bail!(
+                    "{}: unsafe filename at line {}: {filename}",
+                    list.display(),
+                    entry.line
+                );
+            };
+            let hashes = Hash::from(entry.algorithm)
+                .hash_file(&file)
+                .map_err(|e| anyhow!("{}: {e}", file.display()))?;
+            if hashes[0].1 == entry.hash() {
+                Ok(format!("{}: {}", file.display(), Status::Ok))
+            } else {
+                let corruption = corruption(&file, entry)?;
+                Ok(format!(
+                    "{}: {}{corruption}",
+                    file.display(),
+                    entry.failure(&file)
+                ))
+            }
+        })
+        .collect())
+}
+
+/**
+Resolve a filename from a checksum list relative to the list's directory
+
+Returns `None` for absolute filenames and filenames with `..` components, so that an untrusted
+list cannot make fhc read files outside its directory.
+*/
+pub(crate) fn list_file(dir: &Path, filename: &str) -> Option<PathBuf> {
+    let path = Path::new(filename);
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        .then(|| dir.join(path))
+}
+
+/**
+Parse the content of a hash file
+
+Each line is `[LABEL:]digest  filename` (text mode), `[LABEL:]digest *filename` (binary mode),
+a bare `[LABEL:]digest`, or BSD-style `LABEL (filename) = digest` (auto-detected).
+Digests may use any encoding accepted by [`decode_digest`] and, in place of `LABEL:digest`, a
+Subresource Integrity hash (`sha256-base64`) is also accepted.
+A leading byte order mark, CRLF line endings, blank lines, and `#` comment lines are accepted.
+A leading `\` marks a `sha256sum`-style escaped filename (`\\` and `\n`).
+
+If `algorithm` is given, unlabeled digests are assumed to use it and labeled digests must match
+it; otherwise every digest must be labeled.
+
+# Errors
+
+Returns an error naming the offending line if a line is malformed, the label is unknown or
+unexpected, or the digest cannot be decoded, or if there are no entries
+*/
+pub fn parse_sidecar(content: &str, algorithm: Option<Algorithm>) -> Result<Vec<Entry>> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+
+    let mut r = vec![];
+    for (i, line) in content.split('\n').enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.starts_with('#') {
+            parse_comment(line, &mut r)
+                .map_err(|e| anyhow!("malformed sidecar at line {}: {e}", i + 1))?;
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = parse_line(line, i + 1, algorithm)
+            .map_err(|e| anyhow!("malformed sidecar at line {}: {e}", i + 1))?;
+        r.push(entry);
+    }
+
+    if r.is_empty() {
+        bail!("malformed sidecar: no hash found");
+    }
+
+    Ok(r)
+}
+
+/// Apply a `# stat`, `# chunks`, or `# chunk` comment line to the last entry of a hash file
+fn parse_comment(line: &str, entries: &mut [Entry]) -> Result<()> {
+    let mut words = line.trim_start_matches('#').split_whitespace();
+    if let (Some("stat"), Some(size), Some(mtime), None, Some(entry)) = (
+        words.next(),
+        words.next(),
+        words.next(),
+        words.next(),
+        entries.last_mut(),
+    ) {
+        if let (Ok(size), Ok(mtime)) = (size.parse(), mtime.parse()) {
+            entry.size = Some(size);
+            entry.mtime = Some(mtime);
+        }
+        return Ok(());
+    }
+    parse_chunk_comment(line, entries)
+}
+
+/**
+Read and parse a hash file
+
+# Errors
+
+Returns an error if not able to read the hash file or if it is malformed (see [`parse_sidecar`])
+*/
+pub fn parse_sidecar_file<P: AsRef<Path>>(
+    ckfile: P,
+    algorithm: Option<Algorithm>,
+) -> Result<Vec<Entry>> {
+    let ckfile = ckfile.as_ref();
+    parse_sidecar(&read_to_string(ckfile)?, algorithm)
+        .map_err(|e| anyhow!("{}: {e}", ckfile.display()))
+}
+
+/// Parse a single non-empty, non-comment line
+fn parse_line(line: &str, n: usize, algorithm: Option<Algorithm>) -> Result<Entry> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+
+    if let Some((label, filename, hex)) = split_bsd(line) {
+        let Some(found) = Algorithm::from_label(label) else {
+            bail!("unknown algorithm label {label:?}");
+        };
+        check_label(found, algorithm)?;
+        let digest = to_hex_string(&decode_digest(found, hex)?);
+        let filename = if escaped {
+            unescape(filename)?
+        } else {
+            filename.to_string()
+        };
+        return Ok(Entry {
+            algorithm: found,
+            digest,
+            filename: Some(filename),
+            binary: false,
+            line: n,
+            chunks: None,
+            size: None,
+            mtime: None,
+        });
+    }
+
+    let (token, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+    let (algorithm, digest) = if let Some((found, digest)) = parse_sri_token(token)? {
+        check_label(found, algorithm)?;
+        (found, to_hex_string(&digest))
+    } else {
+        let (algorithm, hex) = match (token.split_once(':'), algorithm) {
+            (Some((label, hex)), expected) => {
+                let Some(found) = Algorithm::from_label(label) else {
+                    bail!("unknown algorithm label {label:?}");
+                };
+                check_label(found, expected)?;
+                (found, hex)
+            }
+            (None, Some(expected)) => (expected, token),
+            (None, None) => bail!("missing algorithm label"),
+        };
+        (algorithm, to_hex_string(&decode_digest(algorithm, hex)?))
+    };
+
+    let (binary, filename) = if rest.is_empty() {
+        (false, None)
+    } else if let Some(filename) = rest.strip_prefix('*') {
+        (true, Some(filename))
+    } else {
+        (false, Some(rest.strip_prefix(' ').unwrap_or(rest)))
+    };
+
+    let filename = match filename {
+        Some("") => bail!("missing filename"),
+        Some(filename) if escaped => Some(unescape(filename)?),
+        Some(filename) => Some(filename.to_string()),
+        None => None,
+    };
+
+    Ok(Entry {
+        algorithm,
+        digest,
+        filename,
+        binary,
+        line: n,
+        chunks: None,
+        size: None,
+        mtime: None,
+    })
+}
+
+/**
+Split a BSD-style line into label, filename, and digest
+
+Accepts `LABEL (filename) = digest` as well as OpenSSL's `LABEL(filename)= digest`.
+*/
+fn split_bsd(line: &str) -> Option<(&str, &str, &str)> {
+    let (label, rest) = line.split_once('(')?;
+    let label = label.trim_end();
+    if label.is_empty()
+        || !label
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        || !label.bytes().any(|b| b.is_ascii_alphabetic())
+        || label.bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        return None;
+    }
+    // Digests never contain `)`, but base64 ones may end in `=` padding
+    let (filename, digest) = rest.rsplit_once(')')?;
+    let digest = digest.trim_start().strip_prefix('=')?;
+    Some((label, filename, digest.trim_start()))
+}
+
+/// Check that a found algorithm is the expected one, if any
+fn check_label(found: Algorithm, expected: Option<Algorithm>) -> Result<()> {
+    if let Some(expected) = expected
+        && found != expected
+    {
+        bail!(
+            "expected a {} hash but found {}",
+            expected.label(),
+            found.label()
+        );
+    }
+    Ok(())
+}
+
+/// Escape a filename `sha256sum`-style, returning the line prefix (`\` if escaped)
+fn escape(filename: &str) -> (&'static str, String) {
+    if filename.contains(['\\', '\n', '\r']) {
+        let filename = filename
+            .replace('\\', "\\\\")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r");
+        ("\\", filename)
+    } else {
+        ("", filename.to_string())
+    }
+}
+
+/// Unescape a `sha256sum`-style escaped filename
+fn unescape(filename: &str) -> Result<String> {
+    let mut r = String::new();
+    let mut chars = filename.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('\\') => r.push('\\'),
+                Some('n') => r.push('\n'),
+                Some('r') => r.push('\r'),
+                _ => bail!("invalid escape in filename"),
+            }
+        } else {
+            r.push(c);
+        }
+    }
+    Ok(r)
+}
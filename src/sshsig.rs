@@ -0,0 +1,358 @@
+use {
+    crate::sidecar::check_list_content,
+    anyhow::{Result, anyhow, bail},
+    ssh_key::{HashAlg, LineEnding, PrivateKey, PublicKey, SshSig},
+    std::{
+        fs::{read, read_to_string, write},
+        path::Path,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// Entry of an OpenSSH `allowed_signers` file
+#[derive(Clone, Debug)]
+pub struct AllowedSigner {
+    /// Principal patterns (`*` and `?` wildcards; a leading `!` negates)
+    pub principals: Vec<String>,
+
+    /// Namespaces the key may sign for (any if `None`)
+    pub namespaces: Option<Vec<String>>,
+
+    /// Key is trusted from this Unix time
+    pub valid_after: Option<u64>,
+
+    /// Key is trusted until this Unix time
+    pub valid_before: Option<u64>,
+
+    /// Public key
+    pub key: PublicKey,
+
+    /// Line number
+    pub line: usize,
+}
+
+impl AllowedSigner {
+    /// Check whether a principal matches this entry's patterns
+    #[must_use]
+    pub fn matches(&self, principal: &str) -> bool {
+        let mut matched = false;
+        for pattern in &self.principals {
+            if let Some(pattern) = pattern.strip_prefix('!') {
+                if wildcard(pattern, principal) {
+                    return false;
+                }
+            } else if wildcard(pattern, principal) {
+                matched = true;
+            }
+        }
+        matched
+    }
+
+    /// Check whether this entry allows signatures in a namespace at a Unix time
+    fn allows(&self, namespace: &str, now: u64) -> bool {
+        self.namespaces
+            .as_ref()
+            .is_none_or(|namespaces| namespaces.iter().any(|n| wildcard(n, namespace)))
+            && self.valid_after.is_none_or(|t| now >= t)
+            && self.valid_before.is_none_or(|t| now < t)
+    }
+}
+
+/**
+Parse the content of an OpenSSH `allowed_signers` file
+
+Each line is `principals [options] keytype base64 [comment]`, with comma-separated principals
+and the `namespaces="..."`, `valid-after="..."`, and `valid-before="..."` options (timestamps as
+`YYYYMMDD[HHMM[SS]]`, taken as UTC).
+Blank lines and `#` comment lines are ignored, as are `cert-authority` lines since certificates
+are not supported.
+
+# Errors
+
+Returns an error if a line is malformed
+*/
+pub fn parse_allowed_signers(content: &str) -> Result<Vec<AllowedSigner>> {
+    let mut r = vec![];
+    for (i, line) in content.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let malformed =
+            |what: &str| anyhow!("malformed allowed signer at line {line_number}: {what}");
+        let tokens = tokenize(line);
+        let Some((principals, rest)) = tokens.split_first() else {
+            continue;
+        };
+        let (options, key) = match rest.first() {
+            Some(token) if is_key_type(token) => ("", rest),
+            Some(token) => (token.as_str(), &rest[1..]),
+            None => return Err(malformed("missing key")),
+        };
+
+        let mut signer = AllowedSigner {
+            principals: unquote(principals)
+                .split(',')
+                .map(ToString::to_string)
+                .collect(),
+            namespaces: None,
+            valid_after: None,
+            valid_before: None,
+            key: PublicKey::from_openssh(&key.join(" ")).map_err(|e| malformed(&e.to_string()))?,
+            line: line_number,
+        };
+        let mut cert_authority = false;
+        for option in split_options(options) {
+            let (name, value) = option.split_once('=').unwrap_or((option, ""));
+            let value = unquote(value);
+            match name.to_ascii_lowercase().as_str() {
+                "cert-authority" => cert_authority = true,
+                "namespaces" => {
+                    signer.namespaces = Some(value.split(',').map(ToString::to_string).collect());
+                }
+                "valid-after" => {
+                    signer.valid_after = Some(timestamp(value).ok_or_else(|| malformed(option))?);
+                }
+                "valid-before" => {
+                    signer.valid_before = Some(timestamp(value).ok_or_else(|| malformed(option))?);
+                }
+                _ => return Err(malformed(&format!("unsupported option {name:?}"))),
+            }
+        }
+        if !cert_authority {
+            r.push(signer);
+        }
+    }
+    Ok(r)
+}
+
+/**
+Sign a checksum list, manifest, or any other file with an SSH private key
+
+The signature is written to an adjacent `.sig` file in the SSHSIG format of `ssh-keygen -Y sign`
+(SHA512, ASCII armored) for the given namespace (`file` is customary), and its path is returned.
+
+# Errors
+
+Returns an error if the private key is still encrypted or if not able to read the file or write
+the signature
+*/
+pub fn sign_ssh<P: AsRef<Path>>(file: P, key: &PrivateKey, namespace: &str) -> Result<String> {
+    let file = file.as_ref();
+    if key.is_encrypted() {
+        bail!("the private key must be decrypted first");
+    }
+    let signature = key.sign(namespace, HashAlg::Sha512, &read(file)?)?;
+    let path = format!("{}.sig", file.display());
+    write(&path, signature.to_pem(LineEnding::LF)?)?;
+    Ok(path)
+}
+
+/**
+Verify a file against its adjacent `.sig` SSHSIG signature and return the principal
+
+Like `ssh-keygen -Y verify`, the signature's key must be listed in `allowed_signers` for the given
+namespace and valid now.
+If `identity` is given it must match the entry's principals, otherwise the first principal of the
+matching entry is returned.
+
+# Errors
+
+Returns an error if the signature is missing or malformed, was made by a key that is not allowed
+for the identity and namespace, or does not match the file
+*/
+pub fn verify_ssh<P: AsRef<Path>>(
+    file: P,
+    allowed_signers: &[AllowedSigner],
+    identity: Option<&str>,
+    namespace: &str,
+) -> Result<String> {
+    let file = file.as_ref();
+    verify_ssh_content(file, &read(file)?, allowed_signers, identity, namespace)
+}
+
+/**
+Same as [`verify_ssh`] with the content of the file already read
+
+# Errors
+
+Returns an error if the signature is missing or malformed, was made by a key that is not allowed
+for the identity and namespace, or does not match the content
+*/
+fn verify_ssh_content(
+    file: &Path,
+    content: &[u8],
+    allowed_signers: &[AllowedSigner],
+    identity: Option<&str>,
+    namespace: &str,
+) -> Result<String> {
+    let path = format!("{}.sig", file.display());
+    if !Path::new(&path).exists() {
+        bail!("{}: unsigned (missing {path})", file.display());
+    }
+    let signature = SshSig::from_pem(read(&path)?).map_err(|e| anyhow!("{path}: {e}"))?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let Some(signer) = allowed_signers.iter().find(|signer| {
+        signer.key.key_data() == signature.public_key()
+            && signer.allows(namespace, now)
+            && identity.is_none_or(|identity| signer.matches(identity))
+    }) else {
+        bail!(
+            "{}: bad signature (key not allowed{} in namespace {namespace:?})",
+            file.display(),
+            identity.map(|x| format!(" for {x}")).unwrap_or_default()
+        );
+    };
+    signer
+        .key
+        .verify(namespace, content, &signature)
+        .map_err(|e| anyhow!("{}: bad signature ({e})", file.display()))?;
+    Ok(identity.map_or_else(|| signer.principals[0].clone(), ToString::to_string))
+}
+
+/**
+Verify the SSH signature of a checksum list and then the files it lists
+
+Same as [`check_list`](crate::check_list), but only after the list's signature is verified with
+[`verify_ssh`]; the signed content is what gets verified.
+
+# Errors
+
+Returns an error if the signature does not verify, or if the list is malformed
+*/
+pub fn check_list_ssh<P: AsRef<Path>>(
+    list: P,
+    allowed_signers: &[AllowedSigner],
+    identity: Option<&str>,
+    namespace: &str,
+) -> Result<Vec<Result<String>>> {
+    let list = list.as_ref();
+    let content = read_to_string(list)?;
+    verify_ssh_content(
+        list,
+        content.as_bytes(),
+        allowed_signers,
+        identity,
+        namespace,
+    )?;
+    check_list_content(list, &content)
+}
+
+/// Split a line into whitespace-separated tokens, keeping quoted strings together
+fn tokenize(line: &str) -> Vec<String> {
+    let mut r = vec![];
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                token.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    r.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        r.push(token);
+    }
+    r
+}
+
+/// Split comma-separated options, keeping quoted values together
+fn split_options(options: &str) -> Vec<&str> {
+    let mut r = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in options.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                r.push(&options[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < options.len() {
+        r.push(&options[start..]);
+    }
+    r
+}
+
+/// Remove surrounding double quotes
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+/// Check whether a token is an SSH public key type
+fn is_key_type(token: &str) -> bool {
+    ["ssh-", "ecdsa-", "sk-", "rsa-"]
+        .iter()
+        .any(|prefix| token.starts_with(prefix))
+}
+
+/// Match a pattern with `*` and `?` wildcards
+fn wildcard(pattern: &str, s: &str) -> bool {
+    let (p, s) = (pattern.as_bytes(), s.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while j < s.len() {
+        if i < p.len() && (p[i] == b'?' || p[i] == s[j]) {
+            i += 1;
+            j += 1;
+        } else if i < p.len() && p[i] == b'*' {
+            star = Some((i, j));
+            i += 1;
+        } else if let Some((si, sj)) = star {
+            i = si + 1;
+            j = sj + 1;
+            star = Some((si, sj + 1));
+        } else {
+            return false;
+        }
+    }
+    p[i..].iter().all(|&c| c == b'*')
+}
+
+/// Parse a `YYYYMMDD[HHMM[SS]]` UTC timestamp (optionally ending in `Z`) as Unix time
+fn timestamp(s: &str) -> Option<u64> {
+    let s = s.strip_suffix(['Z', 'z']).unwrap_or(s);
+    if !matches!(s.len(), 8 | 12 | 14) || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field =
+        |range: std::ops::Range<usize>| s.get(range).map_or(Some(0), |x| x.parse::<u64>().ok());
+    let (year, month, day) = (field(0..4)?, field(4..6)?, field(6..8)?);
+    let (hour, minute, second) = (field(8..10)?, field(10..12)?, field(12..14)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    // Days since 1970-01-01 in the proleptic Gregorian calendar
+    let (year, month) = if month <= 2 {
+        (year.checked_sub(1)?, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = year / 400;
+    let day_of_era =
+        (year % 400) * 365 + (year % 400) / 4 - (year % 400) / 100 + (153 * month + 2) / 5 + day
+            - 1;
+    let days = (era * 146_097 + day_of_era).checked_sub(719_468)?;
+    Some(days * 86_400 + hour * 3_600 + minute * 60 + second)
+}
@@ -1,5 +1,23 @@
 use super::*;
 
+/// Create an empty temporary directory for a test
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("fhc-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Get the relative path from the current directory to an absolute path
+fn relative_to_cwd(path: &Path) -> std::path::PathBuf {
+    let cwd = std::env::current_dir().unwrap();
+    let mut r = std::path::PathBuf::new();
+    for _ in cwd.components().skip(1) {
+        r.push("..");
+    }
+    r.join(path.strip_prefix("/").unwrap())
+}
+
 #[test]
 fn blake3_empty() {
     assert_eq!(
@@ -311,3 +329,1983 @@ fn all_file_does_not_exist() {
         "No such file or directory (os error 2)"
     );
 }
+
+#[test]
+fn hasher_all_good() {
+    let mut hasher = Hasher::new(Hash::All);
+    hasher.update(&std::fs::read("tests/lorem.txt").unwrap());
+    assert_eq!(
+        hasher.finalize("tests/lorem.txt").unwrap(),
+        file_all("tests/lorem.txt").unwrap(),
+    );
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_all_good() {
+    assert_eq!(
+        Hash::All.hash_file_async("tests/lorem.txt").await.unwrap(),
+        file_all("tests/lorem.txt").unwrap(),
+    );
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_reader_large() {
+    let data = vec![0x5a; 3 * 1024 * 1024 + 17];
+    let mut hasher = Hasher::new(Hash::Blake3Sha256);
+    hasher.update(&data);
+    assert_eq!(
+        Hash::Blake3Sha256
+            .hash_reader_async(&data[..], "large.bin")
+            .await
+            .unwrap(),
+        hasher.finalize("large.bin").unwrap(),
+    );
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_process_file() {
+    let dir = temp_dir("async-process");
+    let file = dir.join("lorem.txt");
+    std::fs::copy("tests/lorem.txt", &file).unwrap();
+
+    // Saves new hash files like the synchronous API
+    let options = Options {
+        format: Format::Bsd,
+        ..Options::from(Hash::Blake3Sha256)
+    };
+    assert_eq!(
+        options.process_file_async(&file).await.unwrap(),
+        format!(
+            "BLAKE3:{LOREM_B3}  {0}\nSHA256:{LOREM_SHA256}  {0}",
+            file.display()
+        )
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("lorem.txt.b3")).unwrap(),
+        format!("BLAKE3 (lorem.txt) = {LOREM_B3}\n"),
+    );
+
+    // Verifies existing hash files
+    assert_eq!(
+        Hash::Blake3Sha256.process_file_async(&file).await.unwrap(),
+        format!("{}: OK", file.display())
+    );
+    std::fs::write(&file, "modified").unwrap();
+    assert_eq!(
+        Hash::Blake3Sha256.process_file_async(&file).await.unwrap(),
+        Hash::Blake3Sha256.process_file(&file).unwrap()
+    );
+    assert!(
+        Hash::Blake3
+            .process_file_async(dir.join("none.txt"))
+            .await
+            .is_err()
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_file_does_not_exist() {
+    assert_eq!(
+        Hash::Blake3
+            .hash_file_async("tests/none.txt")
+            .await
+            .unwrap_err()
+            .to_string(),
+        "No such file or directory (os error 2)"
+    );
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+#[test]
+fn io_uring_good() {
+    let dir = temp_dir("io-uring");
+    let lorem = dir.join("lorem.txt");
+    let empty = dir.join("empty.txt");
+    std::fs::copy("tests/lorem.txt", &lorem).unwrap();
+    std::fs::copy("tests/empty.txt", &empty).unwrap();
+    let files = [lorem.clone(), dir.join("none.txt"), empty.clone()];
+
+    let results = io_uring(&files, Hash::All);
+    assert_eq!(results.len(), 3);
+    assert_eq!(
+        results[0].as_ref().unwrap(),
+        &Hash::All
+            .hash_file(&lorem)
+            .unwrap()
+            .iter()
+            .map(|(_, hash)| format!("{hash}  {}", lorem.display()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    );
+    assert_eq!(
+        results[1].as_ref().unwrap_err().to_string(),
+        "No such file or directory (os error 2)"
+    );
+
+    let results = io_uring(&files, Hash::All);
+    assert_eq!(
+        results[2].as_ref().unwrap(),
+        &format!("{}: OK", empty.display())
+    );
+
+    // More files than reads in flight, each spanning several reads, hashed on the calling thread
+    // and in other threads
+    let large = dir.join("large.bin");
+    std::fs::write(
+        &large,
+        (0..200_000_u32)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>(),
+    )
+    .unwrap();
+    let files = vec![large.clone(); 70];
+    let options = Options {
+        dry_run: true,
+        ..Options::from(Hash::All)
+    };
+    let expected = Hash::All
+        .hash_file(&large)
+        .unwrap()
+        .iter()
+        .map(|(_, hash)| format!("{hash}  {}", large.display()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    for threads in [1, 2] {
+        let results = thread_pool(threads)
+            .unwrap()
+            .install(|| io_uring(&files, options.clone()));
+        assert!(
+            results
+                .iter()
+                .all(|result| result.as_ref().unwrap() == &expected)
+        );
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Path that records the names of the threads it is used on
+#[derive(Clone)]
+struct TracedPath(
+    &'static str,
+    std::sync::Arc<std::sync::Mutex<Vec<Option<String>>>>,
+);
+
+impl AsRef<Path> for TracedPath {
+    fn as_ref(&self) -> &Path {
+        self.1
+            .lock()
+            .unwrap()
+            .push(std::thread::current().name().map(String::from));
+        Path::new(self.0)
+    }
+}
+
+#[test]
+fn run_in_thread_pool() {
+    let pool = thread_pool(2).unwrap();
+    assert_eq!(pool.current_num_threads(), 2);
+    let results = ProcessOption::RayonParIter.run_in(&["tests/none.txt"], Hash::Blake3, &pool);
+    assert_eq!(
+        results[0].as_ref().unwrap_err().to_string(),
+        "No such file or directory (os error 2)"
+    );
+
+    for option in [
+        ProcessOption::RayonParIter,
+        ProcessOption::SequentialIter,
+        ProcessOption::PerDevice,
+        ProcessOption::LargestFirst,
+    ] {
+        let threads = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let files =
+            ["tests/lorem.txt", "tests/empty.txt"].map(|file| TracedPath(file, threads.clone()));
+        let options = Options {
+            dry_run: true,
+            ..Options::from(Hash::Blake3)
+        };
+        let results = option.run_in(&files, options, &pool);
+        assert!(results.iter().all(Result::is_ok));
+        let threads = threads.lock().unwrap();
+        assert!(!threads.is_empty());
+        assert!(
+            threads
+                .iter()
+                .all(|name| name.as_deref().is_some_and(|name| name.starts_with("fhc-"))),
+            "{option:?}: {threads:?}"
+        );
+    }
+}
+
+#[test]
+fn per_device_input_order() {
+    let dir = temp_dir("per-device");
+    let files = ["c.txt", "a.txt", "none.txt", "b.txt"].map(|name| dir.join(name));
+    for file in files.iter().filter(|file| !file.ends_with("none.txt")) {
+        std::fs::write(file, file.display().to_string()).unwrap();
+    }
+
+    let results = per_device(&files, Hash::Sha256);
+    assert_eq!(results.len(), 4);
+    for (file, result) in files.iter().zip(&results) {
+        if file.ends_with("none.txt") {
+            assert!(result.is_err());
+        } else {
+            assert!(
+                result
+                    .as_ref()
+                    .unwrap()
+                    .ends_with(&file.display().to_string())
+            );
+        }
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn hasher_update_rayon() {
+    let data = vec![0xa5; 3 * 1024 * 1024 + 5];
+    let mut serial = Hasher::new(Hash::All);
+    serial.update(&data);
+    let mut parallel = Hasher::new(Hash::All);
+    parallel.update_rayon(&data);
+    assert_eq!(
+        parallel.finalize("large.bin").unwrap(),
+        serial.finalize("large.bin").unwrap(),
+    );
+}
+
+#[test]
+fn largest_first_input_order() {
+    let dir = temp_dir("largest-first");
+    let files = [("small.txt", 1), ("none.txt", 0), ("large.txt", 100_000)]
+        .map(|(name, size)| (dir.join(name), size));
+    for (file, size) in files.iter().filter(|(_, size)| *size > 0) {
+        std::fs::write(file, vec![b'x'; *size]).unwrap();
+    }
+    let files = files.map(|(file, _)| file);
+
+    // large.txt is above the (test) threshold for hashing with multiple threads in blocks
+    const { assert!(100_000 >= LARGE_FILE_SIZE && 100_000 > LARGE_BUFFER_SIZE) };
+    let results = largest_first(&files, Hash::All);
+    assert!(results[0].as_ref().unwrap().ends_with("small.txt"));
+    assert!(results[1].is_err());
+    assert_eq!(
+        results[2].as_ref().unwrap(),
+        &Hash::All
+            .hash_file(&files[2])
+            .unwrap()
+            .iter()
+            .map(|(_, hash)| format!("{hash}  {}", files[2].display()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    );
+
+    let results = largest_first(&files, Hash::All);
+    assert_eq!(
+        results[2].as_ref().unwrap(),
+        &format!("{}: OK", files[2].display())
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+const LOREM_B3: &str = "726e07a80d19aa22a9cefe8e2aaf565d2aa906d915860fa51b0973f47ec347f6";
+
+#[test]
+fn sidecar_good() {
+    assert_eq!(
+        parse_sidecar(
+            &format!("BLAKE3:{LOREM_B3}  lorem.txt\n"),
+            Some(Algorithm::Blake3)
+        )
+        .unwrap(),
+        vec![Entry {
+            algorithm: Algorithm::Blake3,
+            digest: String::from(LOREM_B3),
+            filename: Some(String::from("lorem.txt")),
+            binary: false,
+            line: 1,
+            chunks: None,
+            size: None,
+            mtime: None,
+        }],
+    );
+}
+
+#[test]
+fn sidecar_bom_crlf_comments_binary() {
+    let content = format!(
+        "\u{feff}# comment\r\n\r\n{} *lorem.txt\r\n",
+        LOREM_B3.to_uppercase()
+    );
+    assert_eq!(
+        parse_sidecar(&content, Some(Algorithm::Sha256)).unwrap(),
+        vec![Entry {
+            algorithm: Algorithm::Sha256,
+            digest: String::from(LOREM_B3),
+            filename: Some(String::from("lorem.txt")),
+            binary: true,
+            line: 3,
+            chunks: None,
+            size: None,
+            mtime: None,
+        }],
+    );
+}
+
+#[test]
+fn sidecar_escaped_filename() {
+    let entries = parse_sidecar(&format!("\\SHA256:{LOREM_B3}  a\\nb\\\\c"), None).unwrap();
+    assert_eq!(entries[0].filename.as_deref(), Some("a\nb\\c"));
+}
+
+#[test]
+fn sidecar_empty() {
+    assert_eq!(
+        parse_sidecar("", Some(Algorithm::Blake3))
+            .unwrap_err()
+            .to_string(),
+        "malformed sidecar: no hash found"
+    );
+}
+
+#[test]
+fn sidecar_wrong_label() {
+    assert_eq!(
+        parse_sidecar(&format!("SHA256:{LOREM_B3}  x"), Some(Algorithm::Blake3))
+            .unwrap_err()
+            .to_string(),
+        "malformed sidecar at line 1: expected a BLAKE3 hash but found SHA256"
+    );
+}
+
+#[test]
+fn sidecar_unknown_label() {
+    assert_eq!(
+        parse_sidecar(&format!("MD5:{LOREM_B3}  x"), None)
+            .unwrap_err()
+            .to_string(),
+        "malformed sidecar at line 1: unknown algorithm label \"MD5\""
+    );
+}
+
+#[test]
+fn sidecar_bad_length() {
+    assert_eq!(
+        parse_sidecar("# c\nSHA512:abcd  x", None)
+            .unwrap_err()
+            .to_string(),
+        "malformed sidecar at line 2: expected 128 hex digits for SHA512 but found 4"
+    );
+}
+
+#[test]
+fn sidecar_bad_hex() {
+    let digest = "g".repeat(64);
+    assert_eq!(
+        parse_sidecar(&format!("BLAKE3:{digest}  x"), None)
+            .unwrap_err()
+            .to_string(),
+        format!("malformed sidecar at line 1: invalid hex digest \"{digest}\"")
+    );
+}
+
+#[test]
+fn sidecar_missing_label() {
+    assert_eq!(
+        parse_sidecar(LOREM_B3, None).unwrap_err().to_string(),
+        "malformed sidecar at line 1: missing algorithm label"
+    );
+}
+
+#[test]
+fn process_file_empty_sidecar() {
+    let dir = temp_dir("empty-sidecar");
+    let file = dir.join("lorem.txt");
+    std::fs::copy("tests/lorem.txt", &file).unwrap();
+    std::fs::write(dir.join("lorem.txt.b3"), "").unwrap();
+    assert_eq!(
+        Hash::Blake3.process_file(&file).unwrap_err().to_string(),
+        format!("{}.b3: malformed sidecar: no hash found", file.display()),
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn process_file_filename_mismatch() {
+    let dir = temp_dir("filename-mismatch");
+    let file = dir.join("lorem.txt");
+    std::fs::copy("tests/lorem.txt", &file).unwrap();
+    std::fs::write(
+        dir.join("lorem.txt.b3"),
+        format!("BLAKE3:{LOREM_B3}  other.txt\n"),
+    )
+    .unwrap();
+    assert_eq!(
+        Hash::Blake3.process_file(&file).unwrap(),
+        format!("{}: FILENAME MISMATCH", file.display()),
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn process_file_multiple_entries() {
+    let dir = temp_dir("multiple-entries");
+    let file = dir.join("lorem.txt");
+    std::fs::copy("tests/lorem.txt", &file).unwrap();
+    std::fs::write(
+        dir.join("lorem.txt.b3"),
+        format!(
+            "BLAKE3:{}  other.txt\nBLAKE3:{LOREM_B3}  ./lorem.txt\n",
+            "0".repeat(64)
+        ),
+    )
+    .unwrap();
+    assert_eq!(
+        Hash::Blake3.process_file(&file).unwrap(),
+        format!("{}: OK", file.display()),
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn check_list_good() {
+    let dir = temp_dir("check-list");
+    std::fs::copy("tests/lorem.txt", dir.join("lorem.txt")).unwrap();
+    std::fs::copy("tests/empty.txt", dir.join("empty.txt")).unwrap();
+    std::fs::write(
+        dir.join("SHA256SUMS"),
+        "32f5be18c7eba9db87f0138604f64fd541f29b6aa940fb6db0b3255e5071bdd5  lorem.txt\n\
+         32f5be18c7eba9db87f0138604f64fd541f29b6aa940fb6db0b3255e5071bdd5 *empty.txt\n",
+    )
+    .unwrap();
+    let results = check_list(dir.join("SHA256SUMS")).unwrap();
+    assert_eq!(
+        results[0].as_ref().unwrap(),
+        &format!("{}: OK", dir.join("lorem.txt").display()),
+    );
+    assert_eq!(
+        results[1].as_ref().unwrap(),
+        &format!("{}: FAILED", dir.join("empty.txt").display()),
+    );
+
+    let lorem = std::fs::canonicalize(dir.join("lorem.txt")).unwrap();
+    std::fs::write(
+        dir.join("SHA256SUMS"),
+        format!(
+            "32f5be18c7eba9db87f0138604f64fd541f29b6aa940fb6db0b3255e5071bdd5  ../lorem.txt\n\
+             32f5be18c7eba9db87f0138604f64fd541f29b6aa940fb6db0b3255e5071bdd5  {}\n\
+             32f5be18c7eba9db87f0138604f64fd541f29b6aa940fb6db0b3255e5071bdd5  ./lorem.txt\n",
+            lorem.display()
+        ),
+    )
+    .unwrap();
+    let results = check_list(dir.join("SHA256SUMS")).unwrap();
+    let list = dir.join("SHA256SUMS");
+    assert_eq!(
+        results[0].as_ref().unwrap_err().to_string(),
+        format!(
+            "{}: unsafe filename at line 1: ../lorem.txt",
+            list.display()
+        ),
+    );
+    assert_eq!(
+        results[1].as_ref().unwrap_err().to_string(),
+        format!(
+            "{}: unsafe filename at line 2: {}",
+            list.display(),
+            lorem.display()
+        ),
+    );
+    assert_eq!(
+        results[2].as_ref().unwrap(),
+        &format!("{}: OK", dir.join("./lorem.txt").display()),
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sidecar_bsd() {
+    let sha512 = "2f8403b95fdb3ed42847fc6da7e3d7dfb7bbfb02a3d5fa04e2b1a585b31d4330dad3d425a3947757602b98246670ca44d8f7a8a62f97f7ce10fb6bf15ddf5b15";
+    let content = format!(
+        "BLAKE3 (lorem.txt) = {LOREM_B3}\nSHA2-512(a (b).txt)= {sha512}\n\\SHA256 (a\\nb) = {LOREM_B3}\n"
+    );
+    let entries = parse_sidecar(&content, None).unwrap();
+    assert_eq!(
+        entries
+            .iter()
+            .map(|entry| (entry.algorithm, entry.filename.as_deref().unwrap()))
+            .collect::<Vec<_>>(),
+        vec![
+            (Algorithm::Blake3, "lorem.txt"),
+            (Algorithm::Sha512, "a (b).txt"),
+            (Algorithm::Sha256, "a\nb"),
+        ],
+    );
+    assert_eq!(entries[1].digest, sha512);
+}
+
+#[test]
+fn format_line() {
+    let hash = format!("SHA256:{LOREM_B3}");
+    assert_eq!(
+        Format::Gnu.line(&hash, "lorem.txt").unwrap(),
+        format!("SHA256:{LOREM_B3}  lorem.txt\n"),
+    );
+    assert_eq!(
+        Format::Bsd.line(&hash, "a\\b").unwrap(),
+        format!("\\SHA256 (a\\\\b) = {LOREM_B3}\n"),
+    );
+    let entries = parse_sidecar(&Format::Bsd.line(&hash, "a\\b").unwrap(), None).unwrap();
+    assert_eq!(entries[0].filename.as_deref(), Some("a\\b"));
+}
+
+#[test]
+fn process_file_bsd() {
+    let dir = temp_dir("process-bsd");
+    let file = dir.join("lorem.txt");
+    std::fs::copy("tests/lorem.txt", &file).unwrap();
+    let options = Options {
+        format: Format::Bsd,
+        ..Options::from(Hash::Blake3Sha256)
+    };
+    options.process_file(&file).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("lorem.txt.b3")).unwrap(),
+        format!("BLAKE3 (lorem.txt) = {LOREM_B3}\n"),
+    );
+    assert_eq!(
+        Hash::Blake3Sha256.process_file(&file).unwrap(),
+        format!("{}: OK", file.display()),
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+const LOREM_SHA256: &str = "32f5be18c7eba9db87f0138604f64fd541f29b6aa940fb6db0b3255e5071bdd5";
+
+const LOREM_SHA256_SRI: &str = "sha256-MvW+GMfrqduH8BOGBPZP1UHym2qpQPttsLMlXlBxvdU=";
+
+#[test]
+fn sri_good() {
+    assert_eq!(
+        to_sri(&format!("SHA256:{LOREM_SHA256}")).unwrap(),
+        LOREM_SHA256_SRI
+    );
+    assert_eq!(
+        to_sri("SHA256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855").unwrap(),
+        "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+    );
+    assert_eq!(
+        to_sri(&format!("BLAKE3:{LOREM_B3}"))
+            .unwrap_err()
+            .to_string(),
+        "SRI does not support BLAKE3"
+    );
+}
+
+#[test]
+fn sri_verify() {
+    let sha512 = to_sri(&file_sha512("tests/lorem.txt").unwrap()[0].1).unwrap();
+    assert_eq!(
+        verify_sri("tests/lorem.txt", LOREM_SHA256_SRI).unwrap(),
+        Status::Ok
+    );
+    assert_eq!(
+        verify_sri("tests/empty.txt", LOREM_SHA256_SRI).unwrap(),
+        Status::Failed
+    );
+    // Only the strongest algorithm counts
+    assert_eq!(
+        verify_sri(
+            "tests/lorem.txt",
+            &format!("{LOREM_SHA256_SRI} sha512-{}?opt", "A".repeat(86))
+        )
+        .unwrap(),
+        Status::Failed
+    );
+    assert_eq!(
+        verify_sri(
+            "tests/lorem.txt",
+            &format!("sha384-abc {LOREM_SHA256_SRI} {sha512}")
+        )
+        .unwrap(),
+        Status::Ok
+    );
+    assert_eq!(
+        verify_sri("tests/lorem.txt", "sha384-abc")
+            .unwrap_err()
+            .to_string(),
+        "no supported SRI hash in \"sha384-abc\""
+    );
+}
+
+#[test]
+fn sidecar_sri() {
+    let entries = parse_sidecar(&format!("{LOREM_SHA256_SRI}  lorem.txt"), None).unwrap();
+    assert_eq!(entries[0].hash(), format!("SHA256:{LOREM_SHA256}"));
+    assert_eq!(
+        parse_sidecar("sha256-AAAA  lorem.txt", None)
+            .unwrap_err()
+            .to_string(),
+        "malformed sidecar at line 1: expected 32 bytes for SHA256 but found 3"
+    );
+}
+
+#[test]
+fn process_file_sri() {
+    let dir = temp_dir("process-sri");
+    let file = dir.join("lorem.txt");
+    std::fs::copy("tests/lorem.txt", &file).unwrap();
+    let options = Options {
+        encoding: Encoding::Sri,
+        ..Options::from(Hash::Sha256)
+    };
+    assert_eq!(
+        options.process_file(&file).unwrap(),
+        format!("{LOREM_SHA256_SRI}  {}", file.display()),
+    );
+    assert_eq!(
+        Hash::Sha256.process_file(&file).unwrap(),
+        format!("{}: OK", file.display()),
+    );
+
+    // SRI hashes have no label for BSD lines, and no empty hash file is left behind
+    let file = dir.join("empty.txt");
+    std::fs::copy("tests/empty.txt", &file).unwrap();
+    let options = Options {
+        format: Format::Bsd,
+        ..options
+    };
+    assert_eq!(
+        options.process_file(&file).unwrap_err().to_string(),
+        "BSD format requires a labeled hash but found \"sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=\"",
+    );
+    assert!(!dir.join("empty.txt.sha256").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn encodings_round_trip() {
+    let dir = temp_dir("encodings-round-trip");
+    for &encoding in Encoding::value_variants() {
+        for &format in Format::value_variants() {
+            let file = dir.join(format!("{encoding:?}-{format:?}.txt"));
+            std::fs::copy("tests/lorem.txt", &file).unwrap();
+            let options = Options {
+                encoding,
+                format,
+                ..Options::from(Hash::Sha256Sha512)
+            };
+            let result = options.process_file(&file);
+            if (encoding, format) == (Encoding::Sri, Format::Bsd) {
+                assert!(result.is_err());
+                continue;
+            }
+            result.unwrap();
+            assert_eq!(
+                Hash::Sha256Sha512.process_file(&file).unwrap(),
+                format!("{}: OK", file.display()),
+                "{encoding:?} {format:?}"
+            );
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn encodings_good() {
+    let hash = format!("SHA256:{LOREM_SHA256}");
+    for (encoding, expected) in [
+        (Encoding::Hex, hash.clone()),
+        (
+            Encoding::UpperHex,
+            format!("SHA256:{}", LOREM_SHA256.to_uppercase()),
+        ),
+        (
+            Encoding::Base64,
+            String::from("SHA256:MvW+GMfrqduH8BOGBPZP1UHym2qpQPttsLMlXlBxvdU="),
+        ),
+        (
+            Encoding::Base32,
+            String::from("SHA256:1mdxf585w9dkn1nznh59dadz4hfm9zv091hky23xpagbqwcbxx9j"),
+        ),
+        (
+            Encoding::Multihash,
+            String::from("SHA256:bciqdf5n6ddd6xko3q7ybhbqe6zh5kqpstnvksqh3nwylgjk6kby33vi"),
+        ),
+        (Encoding::Sri, String::from(LOREM_SHA256_SRI)),
+    ] {
+        let encoded = encoding.encode(&hash).unwrap();
+        assert_eq!(encoded, expected);
+        let entries = parse_sidecar(&format!("{encoded}  lorem.txt"), None).unwrap();
+        assert_eq!(entries[0].hash(), hash);
+    }
+}
+
+#[test]
+fn decode_digest_good() {
+    let empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    for encoded in [
+        "0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73",
+        "zQmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n",
+        "47DEQpj8HBSa-_TImW-5JCeuQeRkm5NMpJWZG3hSuFU",
+        &format!("f1220{empty}"),
+    ] {
+        assert_eq!(
+            to_hex_string(&decode_digest(Algorithm::Sha256, encoded).unwrap()),
+            empty
+        );
+    }
+    let b3 = Encoding::Multihash
+        .encode(&format!("BLAKE3:{LOREM_B3}"))
+        .unwrap();
+    assert_eq!(
+        b3,
+        "BLAKE3:bdyqhe3qhvagrtkrcvhhp5drkv5lf2kvja3mrlbqpuunqs47up3bup5q"
+    );
+    // Multihash for another algorithm is rejected
+    assert!(decode_digest(Algorithm::Sha256, &b3[7..]).is_err());
+}
+
+#[test]
+fn crc32_good() {
+    assert_eq!(file_crc32("tests/lorem.txt").unwrap(), 0x0cc6_7a84);
+    assert_eq!(file_crc32("tests/empty.txt").unwrap(), 0);
+}
+
+#[test]
+fn check_sfv_good() {
+    let dir = temp_dir("check-sfv");
+    std::fs::copy("tests/lorem.txt", dir.join("lorem ipsum.txt")).unwrap();
+    std::fs::copy("tests/empty.txt", dir.join("empty.txt")).unwrap();
+    let sfv = dir.join("files.sfv");
+    std::fs::write(
+        &sfv,
+        "; Generated by WIN-SFV32\r\nlorem ipsum.txt 0cc67a84\r\nempty.txt 00000001\r\n",
+    )
+    .unwrap();
+    let results = check_list(&sfv)
+        .unwrap()
+        .into_iter()
+        .map(|result| result.unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        results,
+        [
+            format!("{}: OK", dir.join("lorem ipsum.txt").display()),
+            format!("{}: FAILED", dir.join("empty.txt").display()),
+        ]
+    );
+
+    // Filenames outside the SFV file's directory are refused
+    std::fs::write(&sfv, "../lorem.txt 0cc67a84\n/etc/hostname 00000000\n").unwrap();
+    let results = check_sfv(&sfv).unwrap();
+    assert_eq!(
+        results[0].as_ref().unwrap_err().to_string(),
+        format!("{}: unsafe filename: ../lorem.txt", sfv.display())
+    );
+    assert_eq!(
+        results[1].as_ref().unwrap_err().to_string(),
+        format!("{}: unsafe filename: /etc/hostname", sfv.display())
+    );
+
+    std::fs::write(&sfv, "lorem.txt 0cc67a8\n").unwrap();
+    assert!(
+        check_sfv(&sfv)
+            .unwrap_err()
+            .to_string()
+            .ends_with("malformed SFV at line 1: \"lorem.txt 0cc67a8\"")
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn hashdeep_round_trip() {
+    let dir = temp_dir("hashdeep");
+    let lorem = dir.join("lorem.txt");
+    std::fs::copy("tests/lorem.txt", &lorem).unwrap();
+
+    // Relative filenames are resolved against the directory the manifest was generated in, not
+    // the one it is saved to
+    let manifest = hashdeep(&["tests/lorem.txt"], Hash::All).unwrap();
+    let mut lines = manifest.lines();
+    assert_eq!(lines.next(), Some("%%%% HASHDEEP-1.0"));
+    assert_eq!(
+        lines.next(),
+        Some("%%%% size,blake3,sha256,sha512,filename")
+    );
+    let cwd = std::env::current_dir().unwrap();
+    assert_eq!(
+        lines.next(),
+        Some(format!("## Invoked from: {}", cwd.display()).as_str())
+    );
+    assert!(manifest.contains(&format!("\n258,{LOREM_B3},{LOREM_SHA256},")));
+    assert!(manifest.ends_with(",tests/lorem.txt\n"));
+
+    let list = dir.join("manifest.txt");
+    std::fs::write(&list, &manifest).unwrap();
+    let results = check_list(&list).unwrap();
+    assert_eq!(
+        results[0].as_ref().unwrap(),
+        &format!("{}: OK", cwd.join("tests/lorem.txt").display())
+    );
+
+    // Without (or with a missing) invocation directory, relative to the manifest
+    std::fs::write(
+        &list,
+        manifest
+            .replace(
+                &format!("## Invoked from: {}", cwd.display()),
+                "## Invoked from: /nonexistent",
+            )
+            .replace("tests/lorem.txt", "lorem.txt"),
+    )
+    .unwrap();
+    let results = check_list(&list).unwrap();
+    assert_eq!(
+        results[0].as_ref().unwrap(),
+        &format!("{}: OK", lorem.display())
+    );
+
+    // Filenames outside the invocation directory are refused
+    std::fs::write(
+        &list,
+        manifest.replace(
+            ",tests/lorem.txt\n",
+            &format!(
+                ",../{}/tests/lorem.txt\n",
+                cwd.file_name().unwrap().display()
+            ),
+        ),
+    )
+    .unwrap();
+    let results = check_list(&list).unwrap();
+    assert!(
+        results[0]
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .ends_with(&format!(
+                "unsafe filename: ../{}/tests/lorem.txt",
+                cwd.file_name().unwrap().display()
+            ))
+    );
+
+    // Unsupported columns are ignored and sizes are checked
+    std::fs::write(
+        &list,
+        format!(
+            "%%%% HASHDEEP-1.0\n%%%% size,md5,sha256,filename\n## $ hashdeep *\n##\n\
+             258,0123456789abcdef0123456789abcdef,{LOREM_SHA256},lorem.txt\n\
+             257,0123456789abcdef0123456789abcdef,{LOREM_SHA256},lorem.txt\n"
+        ),
+    )
+    .unwrap();
+    let results = check_hashdeep(&list)
+        .unwrap()
+        .into_iter()
+        .map(|result| result.unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        results,
+        [
+            format!("{}: OK", lorem.display()),
+            format!("{}: FAILED", lorem.display()),
+        ]
+    );
+
+    std::fs::write(
+        &list,
+        "%%%% HASHDEEP-1.0\n%%%% size,md5,filename\n258,0123456789abcdef0123456789abcdef,lorem.txt\n",
+    )
+    .unwrap();
+    assert!(check_hashdeep(&list).unwrap_err().to_string().ends_with(
+        "no supported hash column in hashdeep manifest (supported: blake3, sha256, sha512)"
+    ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn parse_mtree_good() {
+    let entries = parse_mtree(
+        "#mtree\n\
+         /set type=file uid=0 gid=0 mode=0644\n\
+         . type=dir mode=0755\n\
+         bin type=dir mode=0755\n\
+         \x20   sh mode=0755 size=258 \\\n\
+         \x20       sha256digest=32F5BE18C7EBA9DB87F0138604F64FD541F29B6AA940FB6DB0B3255E5071BDD5\n\
+         \x20   ..\n\
+         my\\040file.txt size=0\n\
+         ./etc/motd uid=1000 link=x\n",
+    )
+    .unwrap();
+    assert_eq!(
+        entries
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect::<Vec<_>>(),
+        [".", "bin", "bin/sh", "my file.txt", "etc/motd"]
+    );
+    assert_eq!(entries[2].mode, Some(0o755));
+    assert_eq!(entries[2].uid, Some(0));
+    assert_eq!(entries[2].line, 5);
+    assert_eq!(entries[2].digests, [format!("SHA256:{LOREM_SHA256}")]);
+    assert_eq!(entries[4].kind.as_deref(), Some("file"));
+    assert_eq!(entries[4].uid, Some(1000));
+
+    assert_eq!(
+        parse_mtree("#mtree\n..\n").unwrap_err().to_string(),
+        "malformed mtree at line 2: \"..\" above the root"
+    );
+}
+
+#[test]
+fn mtree_round_trip() {
+    let dir = temp_dir("mtree");
+    let lorem = dir.join("lorem ipsum.txt");
+    std::fs::copy("tests/lorem.txt", &lorem).unwrap();
+    let relative = relative_to_cwd(&lorem);
+    let spec = mtree(&[&relative], Hash::All).unwrap();
+    let cwd = std::env::current_dir().unwrap();
+    assert!(spec.starts_with(&format!("#mtree\n#\t   tree: {}\n./", cwd.display())));
+    assert!(spec.contains("lorem\\040ipsum.txt type=file "));
+    assert!(spec.contains(&format!(
+        " size=258 sha256digest={LOREM_SHA256} sha512digest="
+    )));
+    assert!(!spec.contains("blake3"));
+    assert!(mtree(&[&lorem], Hash::Blake3).is_err());
+
+    // Paths may not leave the directory the spec was generated in
+    std::fs::create_dir(dir.join("specs")).unwrap();
+    let list = dir.join("specs/spec.mtree");
+    std::fs::write(&list, &spec).unwrap();
+    assert_eq!(
+        check_list(&list).unwrap()[0]
+            .as_ref()
+            .unwrap_err()
+            .to_string(),
+        format!(
+            "{}: unsafe path at line 3: {}",
+            list.display(),
+            relative.display()
+        )
+    );
+
+    // Relative paths are resolved against that directory, not the one the spec is saved to
+    let escaped = relative.display().to_string().replace(' ', "\\040");
+    std::fs::write(
+        &list,
+        spec.replace(
+            &format!("tree: {}", cwd.display()),
+            &format!("tree: {}", dir.display()),
+        )
+        .replace(&format!("./{escaped}"), "./lorem\\040ipsum.txt"),
+    )
+    .unwrap();
+    let results = check_list(&list).unwrap();
+    assert_eq!(
+        results[0].as_ref().unwrap(),
+        &format!("{}: OK", lorem.display())
+    );
+
+    // Same size, different content
+    let mut content = std::fs::read("tests/lorem.txt").unwrap();
+    content[0] ^= 1;
+    std::fs::write(&lorem, &content).unwrap();
+    let results = check_mtree(&list).unwrap();
+    assert_eq!(
+        results[0].as_ref().unwrap(),
+        &format!("{}: FAILED", lorem.display())
+    );
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::copy("tests/lorem.txt", &lorem).unwrap();
+        let mode = std::fs::metadata(&lorem).unwrap().permissions().mode();
+        std::fs::set_permissions(&lorem, std::fs::Permissions::from_mode(mode ^ 0o100)).unwrap();
+        let results = check_mtree(&list).unwrap();
+        assert_eq!(
+            results[0].as_ref().unwrap(),
+            &format!("{}: METADATA CHANGED (mode)", lorem.display())
+        );
+        std::fs::write(&lorem, &content).unwrap();
+        let results = check_mtree(&list).unwrap();
+        assert_eq!(
+            results[0].as_ref().unwrap(),
+            &format!("{}: FAILED, METADATA CHANGED (mode)", lorem.display())
+        );
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn diff_trees() {
+    let dir = temp_dir("diff");
+    let (a, b) = (dir.join("a"), dir.join("b"));
+    for (tree, files) in [
+        (&a, [("x", "1"), ("s/y", "two"), ("only-a", "")]),
+        (&b, [("x", "1"), ("s/y", "three"), ("only-b", "")]),
+    ] {
+        for (file, content) in files {
+            let file = tree.join(file);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, content).unwrap();
+        }
+    }
+    let expected = [
+        ("only-a", Difference::OnlyInA),
+        ("only-b", Difference::OnlyInB),
+        ("s/y", Difference::Different),
+        ("x", Difference::Identical),
+    ]
+    .map(|(file, difference)| (std::path::PathBuf::from(file), difference));
+    let results = diff(&a, &b, &ProcessOption::LargestFirst, Hash::Sha256)
+        .unwrap()
+        .into_iter()
+        .map(|(file, difference)| (file, difference.unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(results, expected);
+    // No hash files are written
+    assert!(!a.join("x.sha256").exists());
+
+    // Checksum list with `./` paths saved from the second tree
+    let manifest = dir.join("b.sha256");
+    std::fs::write(
+        &manifest,
+        "SHA256:6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b  ./x\n\
+         SHA256:8b5b9db0c13db24256c829aa364aa90c6d2eba318b9232a4ab9313b954d3555f  ./s/y\n\
+         SHA256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  only-b\n",
+    )
+    .unwrap();
+    let results = diff_manifest(&a, &manifest, &ProcessOption::RayonParIter)
+        .unwrap()
+        .into_iter()
+        .map(|(file, difference)| (file, difference.unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(results, expected);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn duplicates() {
+    let dir = temp_dir("dupes");
+    let big = vec![7_u8; 10_000];
+    let mut tail = big.clone();
+    tail[9_999] = 8;
+    for (file, content) in [
+        ("a", &big[..]),
+        ("b", &big[..]),
+        ("sub/c", &big[..]),
+        ("tail", &tail[..]),
+        ("x", b"x"),
+        ("sub/y", b"x"),
+        ("z", b"z"),
+        ("empty1", b""),
+        ("empty2", b""),
+    ] {
+        let file = dir.join(file);
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(file, content).unwrap();
+    }
+    let sets = find_duplicates(&[&dir]).unwrap();
+    assert_eq!(sets.len(), 2);
+    assert_eq!(
+        sets[0].files,
+        [dir.join("a"), dir.join("b"), dir.join("sub/c")]
+    );
+    assert_eq!(sets[0].hash, file_blake3(dir.join("a")).unwrap()[0].1);
+    assert_eq!(sets[0].wasted(), 20_000);
+    assert_eq!(sets[1].files, [dir.join("sub/y"), dir.join("x")]);
+    assert!(duplicates_json(&sets[1..]).starts_with(&format!(
+        "{{\"sets\":[{{\"hash\":\"{}\",\"size\":1,\"wasted\":1,\"files\":[\"{}\",",
+        sets[1].hash,
+        dir.join("sub/y").display()
+    )));
+    assert!(duplicates_text(&sets).ends_with("2 duplicate sets, 20001 wasted bytes\n"));
+
+    let results = dedupe(&sets[0], Link::Hardlink);
+    assert_eq!(
+        results[0].as_ref().unwrap(),
+        &format!(
+            "{}: HARDLINKED to {}",
+            dir.join("b").display(),
+            dir.join("a").display()
+        )
+    );
+    assert!(results.iter().all(Result::is_ok));
+    assert_eq!(std::fs::read(dir.join("sub/c")).unwrap(), big);
+
+    // Hard linked files are no longer reported
+    #[cfg(unix)]
+    assert_eq!(find_duplicates(&[&dir]).unwrap().len(), 1);
+
+    // An existing file with the temporary name is left alone
+    std::fs::write(dir.join("x.fhc-dedupe"), "mine").unwrap();
+    assert!(dedupe(&sets[1], Link::Hardlink).iter().all(Result::is_ok));
+    assert_eq!(std::fs::read(dir.join("x.fhc-dedupe")).unwrap(), b"mine");
+    assert!(!dir.join("x.fhc-dedupe.1").exists());
+    assert_eq!(std::fs::read(dir.join("x")).unwrap(), b"x");
+    #[cfg(unix)]
+    assert!(find_duplicates(&[&dir]).unwrap().is_empty());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn tree_digest_good() {
+    let dir = temp_dir("tree");
+    let data = dir.join("data");
+    std::fs::create_dir_all(data.join("sub")).unwrap();
+    std::fs::create_dir_all(data.join("empty")).unwrap();
+    std::fs::copy("tests/lorem.txt", data.join("sub/lorem.txt")).unwrap();
+    std::fs::copy("tests/empty.txt", data.join("b.txt")).unwrap();
+
+    let records = format!(
+        "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262 b.txt\0\
+         {LOREM_B3} sub/lorem.txt\0"
+    );
+    let expected = format!("BLAKE3:{}", blake3::hash(records.as_bytes()));
+    let hashes = tree_digest(&data, Hash::Blake3Sha256, false).unwrap();
+    assert_eq!(
+        hashes[0],
+        (format!("{}.b3", data.display()), expected.clone())
+    );
+    assert_eq!(hashes[1].0, format!("{}.sha256", data.display()));
+
+    // Saved adjacent to the directory, then verified
+    let options = Options::from(Hash::Blake3);
+    let saved = options.process_tree(format!("{}/", data.display()), false);
+    assert_eq!(saved.unwrap(), format!("{expected}  {}", data.display()));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("data.b3")).unwrap(),
+        format!("{expected}  data\n")
+    );
+    assert_eq!(
+        options.process_tree(&data, false).unwrap(),
+        format!("{}: OK", data.display())
+    );
+
+    // Chunk digests are not recorded for directories
+    std::fs::remove_file(dir.join("data.b3")).unwrap();
+    let chunked = Options {
+        chunk_size: Some(1024),
+        ..options.clone()
+    };
+    chunked.process_tree(&data, false).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("data.b3")).unwrap(),
+        format!("{expected}  data\n")
+    );
+    std::fs::rename(data.join("b.txt"), data.join("c.txt")).unwrap();
+    assert_eq!(
+        options.process_tree(&data, false).unwrap(),
+        format!("{}: FAILED", data.display())
+    );
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let with_modes = tree_digest(&data, Hash::Blake3, true).unwrap();
+        assert_ne!(with_modes, tree_digest(&data, Hash::Blake3, false).unwrap());
+
+        // The mode flag is recorded and honored when verifying without it
+        std::fs::remove_file(dir.join("data.b3")).unwrap();
+        options.process_tree(&data, true).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("data.b3")).unwrap(),
+            format!("{}  data\n# modes\n", with_modes[0].1)
+        );
+        assert_eq!(
+            options.process_tree(&data, false).unwrap(),
+            format!("{}: OK", data.display())
+        );
+        let file = data.join("c.txt");
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(
+            options.process_tree(&data, false).unwrap(),
+            format!("{}: FAILED", data.display())
+        );
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn chunked_verification() {
+    let dir = temp_dir("chunks");
+    let file = dir.join("lorem.txt");
+    std::fs::copy("tests/lorem.txt", &file).unwrap();
+    let options = Options {
+        chunk_size: Some(100),
+        ..Options::from(Hash::Blake3Sha256)
+    };
+    options.process_file(&file).unwrap();
+
+    let entries = parse_sidecar_file(dir.join("lorem.txt.sha256"), None).unwrap();
+    let chunks = entries[0].chunks.as_ref().unwrap();
+    assert_eq!((chunks.chunk_size, chunks.size), (100, 258));
+    assert_eq!(chunks.digests.len(), 3);
+    let content = std::fs::read("tests/lorem.txt").unwrap();
+    assert_eq!(
+        format!("SHA256:{}", chunks.digests[2]),
+        sha256_bytes(&content[200..])
+    );
+    assert_eq!(
+        options.process_file(&file).unwrap(),
+        format!("{}: OK", file.display())
+    );
+
+    // Corrupt the first and last chunks
+    let mut corrupted = content.clone();
+    corrupted[5] ^= 1;
+    corrupted[257] ^= 1;
+    std::fs::write(&file, &corrupted).unwrap();
+    assert_eq!(
+        options.process_file(&file).unwrap(),
+        format!("{}: FAILED (corrupted bytes 0-99, 200-257)", file.display())
+    );
+
+    // Truncation
+    std::fs::write(&file, &content[..150]).unwrap();
+    assert_eq!(
+        check_list(dir.join("lorem.txt.b3")).unwrap()[0]
+            .as_ref()
+            .unwrap(),
+        &format!("{}: FAILED (corrupted bytes 100-257)", file.display())
+    );
+
+    // Malformed chunk lines are rejected, unrelated comments are not
+    assert!(parse_sidecar(&format!("BLAKE3:{LOREM_B3}  a\n# chunks are cool\n"), None).is_ok());
+    assert_eq!(
+        parse_sidecar(
+            &format!("BLAKE3:{LOREM_B3}  a\n# chunks 100 258\n# chunk 1 00\n"),
+            None
+        )
+        .unwrap_err()
+        .to_string(),
+        "malformed sidecar at line 3: chunk 1 out of order"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// SHA256 of a byte slice as produced by [`Hash::hash_file`]
+fn sha256_bytes(bytes: &[u8]) -> String {
+    format!("SHA256:{}", to_hex_string(&Sha256::digest(bytes)))
+}
+
+#[cfg(feature = "bao")]
+#[test]
+fn bao_slices() {
+    let dir = temp_dir("bao");
+    let file = dir.join("data.bin");
+    let content = (0..100_000_u32)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    std::fs::write(&file, &content).unwrap();
+
+    let root = write_outboard(&file).unwrap();
+    assert_eq!(root, file_blake3(&file).unwrap()[0].1);
+    assert!(dir.join("data.bin.obao").exists());
+
+    let slice = extract_slice(&file, 40_000, 3_000).unwrap();
+    let verified = decode_slice(&slice, &root, 40_000, 3_000).unwrap();
+    assert_eq!(verified, &content[40_000..43_000]);
+    assert_eq!(
+        verify_slice(&file, &root, 40_000, 3_000).unwrap(),
+        Status::Ok
+    );
+
+    // Corruption is only detected in the slices that cover it
+    let mut corrupted = content.clone();
+    corrupted[90_000] ^= 1;
+    std::fs::write(&file, &corrupted).unwrap();
+    assert_eq!(
+        verify_slice(&file, &root, 40_000, 3_000).unwrap(),
+        Status::Ok
+    );
+    assert_eq!(
+        verify_slice(&file, &root, 89_000, 3_000).unwrap(),
+        Status::Failed
+    );
+    assert!(verify_slice(&file, &format!("SHA256:{LOREM_SHA256}"), 0, 1).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "bao")]
+#[test]
+fn bao_outboard_options() {
+    let dir = temp_dir("bao_options");
+    let file = dir.join("data.bin");
+    let content = (0..100_000_u32)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    std::fs::write(&file, &content).unwrap();
+    let outboard = dir.join("data.bin.obao");
+
+    // Written with new hash files (and chunk digests) in the same read
+    let options = Options {
+        bao: true,
+        chunk_size: Some(30_000),
+        ..Options::from(Hash::Blake3Sha256)
+    };
+    options.process_file(&file).unwrap();
+    let expected = std::fs::read(&outboard).unwrap();
+    let entries = parse_sidecar_file(dir.join("data.bin.b3"), None).unwrap();
+    assert_eq!(entries[0].chunks.as_ref().unwrap().digests.len(), 4);
+    let root = file_blake3(&file).unwrap()[0].1.clone();
+    assert_eq!(
+        verify_slice(&file, &root, 40_000, 3_000).unwrap(),
+        Status::Ok
+    );
+
+    // Kept after a failed verification, rewritten after a successful one
+    let mut corrupted = content.clone();
+    corrupted[90_000] ^= 1;
+    std::fs::write(&file, &corrupted).unwrap();
+    assert!(options.process_file(&file).unwrap().contains("FAILED"));
+    assert_eq!(std::fs::read(&outboard).unwrap(), expected);
+    std::fs::write(&file, &content).unwrap();
+    std::fs::remove_file(&outboard).unwrap();
+    assert!(options.process_file(&file).unwrap().ends_with("OK"));
+    assert_eq!(std::fs::read(&outboard).unwrap(), expected);
+    assert!(!dir.join(".data.bin.obao.tmp").exists());
+
+    // Requires BLAKE3
+    let sha256 = Options {
+        bao: true,
+        ..Options::from(Hash::Sha256)
+    };
+    assert!(sha256.process_file(&file).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "minisign")]
+#[test]
+fn minisign_sidecars() {
+    let dir = temp_dir("minisign");
+    let file = dir.join("lorem.txt");
+    std::fs::copy("tests/lorem.txt", &file).unwrap();
+    let key = minisign::KeyPair::generate_unencrypted_keypair().unwrap();
+    let other = minisign::KeyPair::generate_unencrypted_keypair().unwrap();
+
+    // Saving signs the new hash file
+    let options = Options {
+        signing_key: Some(key.sk.clone()),
+        trusted_key: Some(key.pk.clone()),
+        ..Options::from(Hash::Blake3)
+    };
+    options.process_file(&file).unwrap();
+    let ckfile = dir.join("lorem.txt.b3");
+    assert!(dir.join("lorem.txt.b3.minisig").exists());
+    verify_minisign(&ckfile, &key.pk).unwrap();
+    assert_eq!(
+        options.process_file(&file).unwrap(),
+        format!("{}: OK", file.display())
+    );
+
+    // Wrong key, rewritten hash file, and missing signature are refused
+    let untrusted = Options {
+        trusted_key: Some(other.pk.clone()),
+        ..Options::from(Hash::Blake3)
+    };
+    assert!(untrusted.process_file(&file).is_err());
+    std::fs::write(&ckfile, format!("{}  lorem.txt\n", "0".repeat(64))).unwrap();
+    assert!(
+        options
+            .process_file(&file)
+            .unwrap_err()
+            .to_string()
+            .contains("bad signature")
+    );
+    std::fs::remove_file(dir.join("lorem.txt.b3.minisig")).unwrap();
+    assert!(
+        options
+            .process_file(&file)
+            .unwrap_err()
+            .to_string()
+            .contains("unsigned")
+    );
+
+    // Without a trusted key, signatures are not required
+    assert_eq!(
+        Hash::Blake3.process_file(&file).unwrap(),
+        format!("{}: FAILED", file.display())
+    );
+
+    // Any file can be signed
+    sign_minisign(&file, &other.sk).unwrap();
+    verify_minisign(&file, &other.pk).unwrap();
+    assert!(verify_minisign(&file, &key.pk).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "openpgp")]
+#[test]
+fn openpgp_checksum_lists() {
+    let dir = temp_dir("openpgp");
+    std::fs::copy("tests/lorem.txt", dir.join("lorem.txt")).unwrap();
+    for name in ["SHA256SUMS", "SHA256SUMS.asc", "SHA256SUMS.gpg"] {
+        std::fs::copy(format!("tests/openpgp/{name}"), dir.join(name)).unwrap();
+    }
+    let keys = load_keyring("tests/openpgp/keyring.gpg").unwrap();
+    let other = load_keyring("tests/openpgp/other.asc").unwrap();
+    let ok = vec![format!("{}: OK", dir.join("lorem.txt").display())];
+
+    // Detached and clearsigned
+    for list in ["SHA256SUMS", "SHA256SUMS.asc"] {
+        let list = dir.join(list);
+        assert_eq!(
+            verify_openpgp(&list, &keys).unwrap().trim_end(),
+            format!("{LOREM_SHA256}  lorem.txt")
+        );
+        let results = check_list_openpgp(&list, &keys)
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(results, ok);
+        assert!(
+            check_list_openpgp(&list, &other)
+                .unwrap_err()
+                .to_string()
+                .contains("bad signature")
+        );
+    }
+
+    // An SSH signature is not taken for a detached OpenPGP signature
+    let list = dir.join("SHA256SUMS");
+    std::fs::rename(dir.join("SHA256SUMS.gpg"), dir.join("SHA256SUMS.sign")).unwrap();
+    std::fs::write(
+        dir.join("SHA256SUMS.sig"),
+        "-----BEGIN SSH SIGNATURE-----\n-----END SSH SIGNATURE-----\n",
+    )
+    .unwrap();
+    verify_openpgp(&list, &keys).unwrap();
+    std::fs::rename(dir.join("SHA256SUMS.sign"), dir.join("SHA256SUMS.gpg")).unwrap();
+
+    // Tampered list
+    std::fs::write(&list, format!("{LOREM_SHA256}  other.txt\n")).unwrap();
+    assert!(check_list_openpgp(&list, &keys).is_err());
+
+    // Unsigned list
+    std::fs::remove_file(dir.join("SHA256SUMS.gpg")).unwrap();
+    assert!(
+        verify_openpgp(&list, &keys)
+            .unwrap_err()
+            .to_string()
+            .contains("unsigned")
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "openpgp")]
+#[test]
+fn openpgp_subkeys() {
+    let dir = temp_dir("openpgp_subkeys");
+    std::fs::copy("tests/lorem.txt", dir.join("lorem.txt")).unwrap();
+    std::fs::copy("tests/openpgp/SHA256SUMS", dir.join("SHA256SUMS")).unwrap();
+    let keys = load_keyring("tests/openpgp/subkeys/keyring.gpg").unwrap();
+    let list = dir.join("SHA256SUMS");
+
+    // Only the subkey still bound for signing, unrevoked, and unexpired when it signed verifies
+    for (signature, ok) in [
+        ("good", true),
+        ("auth", false),
+        ("revoked", false),
+        ("expired", false),
+    ] {
+        std::fs::copy(
+            format!("tests/openpgp/subkeys/{signature}.gpg"),
+            dir.join("SHA256SUMS.gpg"),
+        )
+        .unwrap();
+        assert_eq!(verify_openpgp(&list, &keys).is_ok(), ok, "{signature}");
+    }
+
+    // Signed content goes through the same detection as `check_list`
+    let manifest = dir.join("hashdeep.txt.asc");
+    std::fs::copy("tests/openpgp/subkeys/hashdeep.txt.asc", &manifest).unwrap();
+    assert_eq!(
+        check_list_openpgp(&manifest, &keys)
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>(),
+        vec![format!("{}: OK", dir.join("lorem.txt").display())]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn bit_rot() {
+    let dir = temp_dir("bit-rot");
+    let file = dir.join("lorem.txt");
+    std::fs::copy("tests/lorem.txt", &file).unwrap();
+    let options = Options {
+        bit_rot: true,
+        ..Options::from(Hash::Blake3)
+    };
+    options.process_file(&file).unwrap();
+    let ckfile = dir.join("lorem.txt.b3");
+    let entry = parse_sidecar_file(&ckfile, None).unwrap().remove(0);
+    assert_eq!(entry.size, Some(std::fs::metadata(&file).unwrap().len()));
+    assert!(entry.mtime.is_some());
+    assert_eq!(entry.failure(&file), Status::Corrupted);
+
+    // Same size and mtime but different content
+    let mtime = std::fs::metadata(&file).unwrap().modified().unwrap();
+    let mut content = std::fs::read(&file).unwrap();
+    content[0] ^= 1;
+    std::fs::write(&file, &content).unwrap();
+    File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_modified(mtime)
+        .unwrap();
+    let corrupted = format!("{}: FAILED (possible corruption)", file.display());
+    assert_eq!(options.process_file(&file).unwrap(), corrupted);
+    assert_eq!(
+        check_list(&ckfile).unwrap()[0].as_ref().unwrap(),
+        &corrupted
+    );
+
+    // Edited
+    std::fs::write(&file, "edited").unwrap();
+    assert_eq!(
+        Hash::Blake3.process_file(&file).unwrap(),
+        format!("{}: FAILED (modified)", file.display())
+    );
+
+    // Without recorded metadata
+    std::fs::remove_file(&ckfile).unwrap();
+    std::fs::copy("tests/lorem.txt", &file).unwrap();
+    Hash::Blake3.process_file(&file).unwrap();
+    std::fs::write(&file, "edited").unwrap();
+    assert_eq!(
+        Hash::Blake3.process_file(&file).unwrap(),
+        format!("{}: FAILED", file.display())
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn watch_events() {
+    let dir = temp_dir("watch");
+    let drop = dir.join("drop");
+    std::fs::create_dir_all(drop.join("sub")).unwrap();
+    std::fs::create_dir_all(dir.join("outside/moved")).unwrap();
+    let mut watcher = Watcher::new(&[&drop]).unwrap();
+    let options = Options::from(Hash::Blake3);
+    let events = |watcher: &mut Watcher, n: usize| {
+        let mut r = vec![];
+        watcher
+            .run(&options, |event| {
+                r.push(event);
+                r.len() < n
+            })
+            .unwrap();
+        r
+    };
+
+    // New files are hashed, including those of moved directories; dot files, outboards and
+    // signatures are ignored, and directories that can't be watched are reported
+    let (a, b, gone) = (
+        drop.join("sub/a.txt"),
+        drop.join("moved/b.txt"),
+        drop.join("gone"),
+    );
+    std::fs::create_dir(&gone).unwrap();
+    std::fs::remove_dir(&gone).unwrap();
+    std::fs::write(drop.join(".tmp"), "tmp").unwrap();
+    std::fs::write(drop.join("c.obao"), "obao").unwrap();
+    std::fs::write(drop.join("c.sig"), "sig").unwrap();
+    std::fs::copy("tests/lorem.txt", &a).unwrap();
+    std::fs::write(dir.join("outside/moved/b.txt"), "b").unwrap();
+    std::fs::rename(dir.join("outside/moved"), drop.join("moved")).unwrap();
+    let r = events(&mut watcher, 3);
+    assert_eq!(
+        r.iter()
+            .map(|event| (event.file.clone(), event.action))
+            .collect::<Vec<_>>(),
+        vec![
+            (gone.clone(), Action::Watched),
+            (a.clone(), Action::Hashed),
+            (b.clone(), Action::Hashed)
+        ]
+    );
+    let e = r[0].result.as_ref().unwrap_err().to_string();
+    assert!(e.starts_with(&gone.display().to_string()), "{e}");
+    assert!(r[0].json().contains("\"event\":\"error\""));
+    let r = r.into_iter().skip(1).collect::<Vec<_>>();
+    assert_eq!(
+        r[0].json(),
+        format!(
+            "{{\"time\":{},\"event\":\"hashed\",\"path\":{},\"hashes\":[\"BLAKE3:{LOREM_B3}\"]}}",
+            r[0].time,
+            json::json_string(&a.display().to_string())
+        )
+    );
+    assert!(drop.join("sub/a.txt.b3").exists());
+    assert!(!drop.join(".tmp.b3").exists());
+    assert!(!drop.join("c.obao.b3").exists());
+    assert!(!drop.join("c.sig.b3").exists());
+
+    // Modified files are re-verified
+    std::fs::write(&a, "modified").unwrap();
+    let r = events(&mut watcher, 1);
+    assert_eq!(r[0].action, Action::Verified);
+    assert_eq!(
+        r[0].result.as_ref().unwrap(),
+        &format!("{}: FAILED", a.display())
+    );
+    assert!(r[0].json().ends_with(",\"status\":\"FAILED\"}"));
+
+    // Queue overflows have no path
+    let overflow = WatchEvent {
+        time: 1,
+        file: std::path::PathBuf::new(),
+        action: Action::Overflowed,
+        result: Err(anyhow::anyhow!("too many events")),
+        hashes: vec![],
+    };
+    assert_eq!(
+        overflow.json(),
+        "{\"time\":1,\"event\":\"overflow\",\"error\":\"too many events\"}"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn database_scans() {
+    let dir = temp_dir("database");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(tree.join("sub")).unwrap();
+    let (a, b, c) = (
+        tree.join("a.txt"),
+        tree.join("sub/b.txt"),
+        tree.join("c.txt"),
+    );
+    std::fs::copy("tests/lorem.txt", &a).unwrap();
+    std::fs::write(&b, "b").unwrap();
+    let path = dir.join("fhc.db");
+    let process = ProcessOption::SequentialForLoop;
+    let scan = |database: &mut Database, full| {
+        database
+            .scan(&[&tree], &process, Hash::Blake3, full)
+            .unwrap()
+            .into_iter()
+            .map(|(file, event)| (file, event.unwrap()))
+            .collect::<Vec<_>>()
+    };
+
+    // First scan records every file, later scans only changes
+    let mut database = Database::open(&path).unwrap();
+    assert_eq!(
+        scan(&mut database, false),
+        vec![(a.clone(), Event::New), (b.clone(), Event::New)]
+    );
+    assert_eq!(
+        database.files()[a.as_path()].hashes,
+        vec![format!("BLAKE3:{LOREM_B3}")]
+    );
+    assert!(scan(&mut database, false).is_empty());
+    assert!(database.status(&[&tree]).unwrap().is_empty());
+
+    std::fs::write(&a, "modified").unwrap();
+    std::fs::remove_file(&b).unwrap();
+    std::fs::write(&c, "c").unwrap();
+    let changes = vec![
+        (a.clone(), Event::Modified),
+        (c.clone(), Event::New),
+        (b.clone(), Event::Deleted),
+    ];
+    assert_eq!(database.status(&[&tree]).unwrap(), changes);
+    assert_eq!(scan(&mut database, false), changes);
+
+    // Full scans verify unchanged files
+    let mtime = std::fs::metadata(&c).unwrap().modified().unwrap();
+    std::fs::write(&c, "x").unwrap();
+    File::options()
+        .write(true)
+        .open(&c)
+        .unwrap()
+        .set_modified(mtime)
+        .unwrap();
+    assert!(database.status(&[&tree]).unwrap().is_empty());
+    // A failed file does not become the baseline of the next full scan
+    for _ in 0..2 {
+        assert_eq!(
+            scan(&mut database, true),
+            vec![(a.clone(), Event::Ok), (c.clone(), Event::Failed)]
+        );
+    }
+
+    // History survives reopening
+    let database = Database::open(&path).unwrap();
+    assert_eq!(database.last_scan(), 4);
+    assert_eq!(database.records().len(), 9);
+    assert!(
+        database.records()[0]
+            .json()
+            .starts_with("{\"scan\":1,\"time\":")
+    );
+    assert!(Database::open("tests/lorem.txt").is_err());
+
+    // A database inside the scanned tree is not scanned itself
+    let mut inside = Database::open(relative_to_cwd(&tree.join("fhc.db"))).unwrap();
+    assert_eq!(scan(&mut inside, false).len(), 2);
+    assert!(tree.join("fhc.db").exists());
+    assert!(scan(&mut inside, false).is_empty());
+    assert!(inside.status(&[&tree]).unwrap().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(all(unix, feature = "xattr"))]
+#[test]
+fn xattr_cache() {
+    let dir = temp_dir("xattr-cache");
+    let file = dir.join("lorem.txt");
+    std::fs::copy("tests/lorem.txt", &file).unwrap();
+    if xattr::set(&file, "user.fhc.test", b"").is_err() {
+        // No user extended attributes on this file system
+        std::fs::remove_dir_all(&dir).unwrap();
+        return;
+    }
+    let options = Options {
+        cache: true,
+        ..Options::from(Hash::Blake3Sha256)
+    };
+    let ok = format!("{}: OK", file.display());
+
+    // Hashing caches the digests
+    assert!(cached_hashes(&file, Hash::Blake3Sha256).is_none());
+    options.process_file(&file).unwrap();
+    assert_eq!(
+        cached_hashes(&file, Hash::Blake3Sha256).unwrap(),
+        Hash::Blake3Sha256.hash_file(&file).unwrap()
+    );
+    assert_eq!(options.process_file(&file).unwrap(), ok);
+
+    // Cached digests are trusted unless paranoid
+    let forged = std::str::from_utf8(&xattr::get(&file, "user.fhc.b3").unwrap().unwrap())
+        .unwrap()
+        .replace(LOREM_B3, &"0".repeat(64));
+    xattr::set(&file, "user.fhc.b3", forged.as_bytes()).unwrap();
+    assert_eq!(
+        options.process_file(&file).unwrap(),
+        format!("{}: FAILED", file.display())
+    );
+    let paranoid = Options {
+        paranoid: true,
+        ..options.clone()
+    };
+    assert_eq!(paranoid.process_file(&file).unwrap(), ok);
+    assert_eq!(options.process_file(&file).unwrap(), ok);
+
+    // Changed metadata invalidates the cache
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&file)
+        .unwrap()
+        .write_all(b"x")
+        .unwrap();
+    assert!(cached_hashes(&file, Hash::Blake3).is_none());
+    assert_eq!(
+        options.process_file(&file).unwrap(),
+        format!("{}: FAILED", file.display())
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "ssh")]
+#[test]
+fn ssh_signatures() {
+    let dir = temp_dir("ssh");
+    std::fs::copy("tests/lorem.txt", dir.join("lorem.txt")).unwrap();
+    for name in ["SHA256SUMS", "SHA256SUMS.sig"] {
+        std::fs::copy(format!("tests/ssh/{name}"), dir.join(name)).unwrap();
+    }
+    let list = dir.join("SHA256SUMS");
+    let allowed =
+        parse_allowed_signers(&std::fs::read_to_string("tests/ssh/allowed_signers").unwrap())
+            .unwrap();
+    assert_eq!(allowed.len(), 2);
+    assert!(allowed[0].matches("developer@example.com"));
+    assert!(!allowed[0].matches("other@example.com"));
+
+    // Signature made by `ssh-keygen -Y sign`
+    assert_eq!(
+        verify_ssh(&list, &allowed, None, "file").unwrap(),
+        "release@example.com"
+    );
+    assert_eq!(
+        verify_ssh(&list, &allowed, Some("dev1@example.com"), "file").unwrap(),
+        "dev1@example.com"
+    );
+    assert!(verify_ssh(&list, &allowed, Some("other@example.com"), "file").is_err());
+    assert!(verify_ssh(&list, &allowed, None, "git").is_err());
+    let results = check_list_ssh(&list, &allowed, None, "file")
+        .unwrap()
+        .into_iter()
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        results,
+        vec![format!("{}: OK", dir.join("lorem.txt").display())]
+    );
+
+    // Signed by a key that is not allowed
+    let key =
+        ssh_key::PrivateKey::random(&mut ssh_key::rand_core::OsRng, ssh_key::Algorithm::Ed25519)
+            .unwrap();
+    sign_ssh(&list, &key, "release").unwrap();
+    assert_eq!(
+        verify_ssh(&list, &allowed, None, "release")
+            .unwrap_err()
+            .to_string(),
+        format!(
+            "{}: bad signature (key not allowed in namespace \"release\")",
+            list.display()
+        )
+    );
+    let public = key.public_key().to_openssh().unwrap();
+    let any = parse_allowed_signers(&format!("* {public}")).unwrap();
+    verify_ssh(&list, &any, Some("anyone"), "release").unwrap();
+    let expired = parse_allowed_signers(&format!("* valid-before=\"20200101\" {public}")).unwrap();
+    assert_eq!(expired[0].valid_before, Some(1_577_836_800));
+    assert!(verify_ssh(&list, &expired, None, "release").is_err());
+
+    // Tampered and unsigned lists
+    std::fs::write(&list, format!("{LOREM_SHA256}  other.txt\n")).unwrap();
+    assert!(verify_ssh(&list, &any, None, "release").is_err());
+    std::fs::remove_file(dir.join("SHA256SUMS.sig")).unwrap();
+    assert!(
+        verify_ssh(&list, &any, None, "release")
+            .unwrap_err()
+            .to_string()
+            .contains("unsigned")
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn in_toto_statements() {
+    let dir = temp_dir("in-toto");
+    let file = dir.join("lorem.txt");
+    std::fs::copy("tests/lorem.txt", &file).unwrap();
+
+    let statement = in_toto(&[&file], Hash::Sha256Sha512, RELEASE_PREDICATE_TYPE).unwrap();
+    assert!(statement.starts_with(&format!(
+        "{{\"_type\":\"https://in-toto.io/Statement/v1\",\"subject\":[{{\"name\":\"lorem.txt\",\"digest\":{{\"sha256\":\"{LOREM_SHA256}\",\"sha512\":\""
+    )));
+    assert!(statement.ends_with(
+        "\"predicateType\":\"https://in-toto.io/attestation/release/v0.1\",\"predicate\":{}}\n"
+    ));
+    assert_eq!(
+        Manifest::InToto.generate(&[&file], Hash::Sha256).unwrap(),
+        in_toto(&[&file], Hash::Sha256, RELEASE_PREDICATE_TYPE).unwrap()
+    );
+
+    // Bare statement and JSON Lines of DSSE envelopes, with subjects relative to the attestation
+    let ok = vec![format!("{}: OK", file.display())];
+    let bare = dir.join("statement.json");
+    std::fs::write(&bare, &statement).unwrap();
+    let envelope = format!(
+        "{{\"payloadType\":\"application/vnd.in-toto+json\",\"payload\":\"{}\",\"signatures\":[]}}",
+        encoding::base64(
+            format!(
+                "{{\"_type\":\"https://in-toto.io/Statement/v1\",\"subject\":[{{\"name\":\"lorem.txt\",\"digest\":{{\"SHA256\":\"{}\",\"gitCommit\":\"abc\"}}}}],\"predicateType\":\"x\",\"predicate\":{{}}}}",
+                LOREM_SHA256.to_ascii_uppercase()
+            )
+            .as_bytes()
+        )
+    );
+    let jsonl = dir.join("provenance.intoto.jsonl");
+    std::fs::write(&jsonl, format!("{envelope}\n{envelope}\n")).unwrap();
+    for (attestation, expected) in [(&bare, ok.clone()), (&jsonl, [ok.clone(), ok].concat())] {
+        let results = check_list(attestation)
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(results, expected);
+    }
+
+    // Modified file
+    std::fs::write(&file, "modified").unwrap();
+    assert_eq!(
+        check_in_toto(&bare).unwrap()[0].as_ref().unwrap(),
+        &format!("{}: FAILED", file.display())
+    );
+
+    // Relative inputs are named relative to their common parent directory, where the attestation
+    // is saved
+    let dist = dir.join("dist");
+    std::fs::create_dir_all(dist.join("sub")).unwrap();
+    std::fs::copy("tests/lorem.txt", dist.join("a.txt")).unwrap();
+    std::fs::copy("tests/empty.txt", dist.join("sub/b.txt")).unwrap();
+    let files = [
+        relative_to_cwd(&dist.join("a.txt")),
+        relative_to_cwd(&dist.join("sub/./b.txt")),
+    ];
+    let statement = in_toto(&files, Hash::Sha256, RELEASE_PREDICATE_TYPE).unwrap();
+    assert!(statement.contains("\"subject\":[{\"name\":\"a.txt\","));
+    assert!(statement.contains("{\"name\":\"sub/b.txt\","));
+    let attestation = dist.join("attestation.json");
+    std::fs::write(&attestation, &statement).unwrap();
+    assert_eq!(
+        check_in_toto(relative_to_cwd(&attestation))
+            .unwrap()
+            .into_iter()
+            .map(|result| result.unwrap().ends_with(": OK"))
+            .collect::<Vec<_>>(),
+        [true, true]
+    );
+
+    // Subjects outside the attestation's directory are refused
+    let escape = dist.join("sub/escape.json");
+    std::fs::write(
+        &escape,
+        statement.replace("\"name\":\"a.txt\"", "\"name\":\"../a.txt\""),
+    )
+    .unwrap();
+    assert_eq!(
+        check_in_toto(&escape).unwrap()[0]
+            .as_ref()
+            .unwrap_err()
+            .to_string(),
+        format!("{}: unsafe subject name: ../a.txt", escape.display())
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn json_parse() {
+    let value =
+        json::parse(r#" {"a": [1, -2.5e3, true, null], "b\u00e9\ud83d\ude00": "x\"\n"} "#).unwrap();
+    assert_eq!(
+        value.get("a"),
+        Some(&json::Value::Array(vec![
+            json::Value::Number(1.0),
+            json::Value::Number(-2500.0),
+            json::Value::Bool(true),
+            json::Value::Null,
+        ]))
+    );
+    assert_eq!(
+        value.get("bé😀").and_then(json::Value::as_str),
+        Some("x\"\n")
+    );
+    for invalid in ["", "{", "[1,]", "{\"a\" 1}", "\"\\x\"", "tru", "{} {}"] {
+        assert!(json::parse(invalid).is_err(), "{invalid:?}");
+    }
+}
@@ -0,0 +1,160 @@
+use {
+    crate::{Hash, Hasher, Options, diff::walk},
+    anyhow::{Result, anyhow, bail},
+    rayon::prelude::*,
+    std::path::{Path, PathBuf},
+};
+
+/**
+Calculate the tree digest of a directory and return the hash(es) as `(ckfile, hash)` tuples
+
+The directory is walked recursively (regular files only; symbolic links are not followed and
+empty directories do not contribute).
+For each algorithm of `hash`, every file is hashed with [`Hash::hash_file`] and one record per
+file is built from its lowercase hex digest, its permission bits as octal (only if `modes`), and
+its path relative to the directory with `/` separators:
+
+```text
+<digest> <path>\0
+<digest> <mode> <path>\0
+```
+
+The records are concatenated in byte order of the paths and hashed with the same algorithm.
+The hash file of the directory itself is adjacent to it (e.g. `data.b3` for `data/`).
+
+# Errors
+
+Returns an error if not able to read the directory or any of its files, if a path is not valid
+UTF-8, or if `modes` is requested on a platform without Unix permissions
+*/
+pub fn tree_digest<P: AsRef<Path>>(
+    dir: P,
+    hash: Hash,
+    modes: bool,
+) -> Result<Vec<(String, String)>> {
+    let dir = tree_root(dir.as_ref())?;
+    let mut files = walk(&dir)?
+        .into_iter()
+        .map(|file| {
+            let path = file
+                .to_str()
+                .ok_or_else(|| anyhow!("{}: path is not valid UTF-8", file.display()))?
+                .replace(std::path::MAIN_SEPARATOR, "/");
+            Ok((path, file))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let records = files
+        .par_iter()
+        .map(|(path, file)| {
+            let file = dir.join(file);
+            let hashes = hash
+                .hash_file(&file)
+                .map_err(|e| anyhow!("{}: {e}", file.display()))?;
+            let mode = if modes {
+                let mode = mode(&file).map_err(|e| anyhow!("{}: {e}", file.display()))?;
+                format!("{mode:o} ")
+            } else {
+                String::new()
+            };
+            Ok(hashes
+                .into_iter()
+                .map(|(_, hash)| {
+                    let digest = hash.split_once(':').map_or(hash.as_str(), |x| x.1);
+                    format!("{digest} {mode}{path}\0")
+                })
+                .collect::<Vec<_>>())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut r = vec![];
+    for (i, algorithm) in hash.algorithms().into_iter().enumerate() {
+        let mut hasher = Hasher::new(Hash::from(algorithm));
+        for record in &records {
+            hasher.update(record[i].as_bytes());
+        }
+        r.extend(hasher.finalize(&dir)?);
+    }
+    Ok(r)
+}
+
+/// Normalize a directory path so that its hash file is adjacent to it
+fn tree_root(dir: &Path) -> Result<PathBuf> {
+    if !dir.is_dir() {
+        bail!("{}: not a directory", dir.display());
+    }
+    let dir = dir.components().collect::<PathBuf>();
+    let dir = if dir.file_name().is_some() {
+        dir
+    } else {
+        dir.canonicalize()?
+    };
+    if dir.file_name().is_none() {
+        bail!(
+            "{}: cannot save a hash file for this directory",
+            dir.display()
+        );
+    }
+    Ok(dir)
+}
+
+/// Get the permission bits of a file
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn mode(file: &Path) -> Result<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(file.metadata()?.permissions().mode() & 0o7777)
+}
+
+/// Get the permission bits of a file (unavailable)
+#[cfg(not(unix))]
+fn mode(_file: &Path) -> Result<u32> {
+    bail!("file modes are only supported on Unix")
+}
+
+impl Options {
+    /**
+    Process a directory as a whole
+
+    Same as [`Options::process_file`], but with the directory's tree digest (see
+    [`tree_digest`]) and the hash file(s) adjacent to the directory.
+    If `modes`, new hash files record it with a `# modes` comment line; existing hash files are
+    verified with or without permission bits as they record, regardless of `modes`.
+    [`Options::chunk_size`] is ignored.
+
+    # Errors
+
+    Returns an error if not able to process the given directory
+    */
+    pub fn process_tree<P: AsRef<Path>>(&self, dir: P, modes: bool) -> Result<String> {
+        let dir = tree_root(dir.as_ref())?;
+        let modes = self.recorded_modes(&dir)?.unwrap_or(modes);
+        let hashes = tree_digest(&dir, self.hash, modes)?;
+        let comment = if modes { "# modes\n" } else { "" };
+        // Chunk digests are per file, so a directory's hash files never record them
+        let options = Options {
+            chunk_size: None,
+            ..self.clone()
+        };
+        options.check_or_save_with(&dir, &hashes, None, comment)
+    }
+
+    /**
+    Whether the existing hash file(s) of a directory record permission bits, or `None` if a hash
+    file does not exist yet (or on a dry run)
+
+    # Errors
+
+    Returns an error if not able to read a hash file
+    */
+    fn recorded_modes(&self, dir: &Path) -> Result<Option<bool>> {
+        let ckfiles = self.ckfiles(dir);
+        if self.dry_run || !ckfiles.iter().all(|ckfile| ckfile.exists()) {
+            return Ok(None);
+        }
+        Ok(Some(std::fs::read_to_string(&ckfiles[0])?.lines().any(
+            |line| line.starts_with('#') && line.trim_start_matches('#').trim() == "modes",
+        )))
+    }
+}
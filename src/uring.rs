@@ -0,0 +1,274 @@
+use {
+    crate::{Hasher, Options, Process, rayon_par_iter_by},
+    ::io_uring::{IoUring, opcode, types},
+    anyhow::{Result, anyhow},
+    rayon::Yield,
+    std::{
+        fs::File,
+        io::{Error, ErrorKind},
+        os::fd::AsRawFd,
+        path::Path,
+        sync::mpsc::{Receiver, channel},
+    },
+};
+
+/// Maximum number of reads in flight
+const URING_QUEUE_DEPTH: usize = 64;
+
+/// Size of each read buffer
+const URING_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A file being read
+struct Job {
+    index: usize,
+    file: File,
+    hasher: Hasher,
+    offset: u64,
+    buffer: Vec<u8>,
+}
+
+/// A job handed back after hashing its last completed buffer
+enum Hashed<O> {
+    /// More of the file is to be read
+    Read(Box<Job>),
+
+    /// The file is processed: its index, result, and freed buffer
+    Done(usize, Result<O>, Vec<u8>),
+}
+
+/**
+Process files with the given hash algorithm via Linux `io_uring`
+
+Keeps up to 64 reads in flight across files (one per file) and hashes each completed buffer with
+the file's [`Hasher`] (as [`file_all`] and the other `file_*` functions do) in the current Rayon
+thread pool, so the calling thread only submits reads while completed buffers are hashed in
+parallel (or on the calling thread if the pool has a single thread).
+If `io_uring` is unavailable, files are processed as by [`rayon_par_iter`] instead.
+Results are returned in input order.
+
+[`file_all`]: crate::file_all
+[`rayon_par_iter`]: crate::rayon_par_iter
+*/
+pub fn io_uring<P: AsRef<Path> + Clone + Send + Sync + 'static, O: Into<Options>>(
+    files: &[P],
+    options: O,
+) -> Vec<Result<String>> {
+    io_uring_by(files, &options.into())
+}
+
+/// Same as [`io_uring`] with any [`Process`]
+pub(crate) fn io_uring_by<P: AsRef<Path> + Clone + Send + Sync + 'static, T: Process>(
+    files: &[P],
+    process: &T,
+) -> Vec<Result<T::Output>> {
+    let depth = files.len().clamp(1, URING_QUEUE_DEPTH);
+    let Ok(ring) = IoUring::new(u32::try_from(depth).unwrap_or(u32::MAX)) else {
+        // io_uring is unavailable (e.g. an old kernel, seccomp, or the io_uring_disabled sysctl)
+        return rayon_par_iter_by(files, process);
+    };
+    let mut r = files.iter().map(|_| None).collect::<Vec<_>>();
+
+    match run(ring, depth, files, process, &mut r) {
+        Ok(()) => r
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(anyhow!("io_uring read not completed"))))
+            .collect(),
+        Err(e) => r
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(anyhow!("io_uring: {e}"))))
+            .collect(),
+    }
+}
+
+/// Drive the ring until all files are processed or the ring fails
+fn run<P: AsRef<Path> + Sync, T: Process>(
+    mut ring: IoUring,
+    depth: usize,
+    files: &[P],
+    process: &T,
+    r: &mut [Option<Result<T::Output>>],
+) -> std::io::Result<()> {
+    // Jobs with a read in flight, by slot (the read's user data)
+    let mut slots: Vec<Option<Job>> = (0..depth).map(|_| None).collect();
+    let mut buffers = vec![vec![0_u8; URING_BUFFER_SIZE]; depth];
+    let (tx, rx) = channel();
+    let mut next = 0;
+    let mut in_flight = 0;
+    let mut hashing = 0;
+    // Handing buffers to other threads only pays off if there are any
+    let parallel = rayon::current_num_threads() > 1;
+
+    let result = rayon::in_place_scope(|scope| {
+        loop {
+            // Start reading the next files while there are free buffers
+            while next < files.len()
+                && let Some(buffer) = buffers.pop()
+            {
+                let index = next;
+                next += 1;
+                match File::open(files[index].as_ref()) {
+                    Ok(file) => {
+                        let job = Job {
+                            index,
+                            file,
+                            hasher: Hasher::new(process.options().hash),
+                            offset: 0,
+                            buffer,
+                        };
+                        submit(&mut ring, &mut slots, job)?;
+                        in_flight += 1;
+                    }
+                    Err(e) => {
+                        r[index] = Some(Err(e.into()));
+                        buffers.push(buffer);
+                    }
+                }
+            }
+
+            // Queue the next reads of files whose last buffer has been hashed
+            let mut hashed = rx.try_iter().collect::<Vec<_>>();
+            if hashed.is_empty() && in_flight == 0 {
+                if hashing == 0 {
+                    return Ok(());
+                }
+                hashed.extend(wait(&rx));
+            }
+            for job in hashed {
+                hashing -= 1;
+                match job {
+                    Hashed::Read(job) => {
+                        submit(&mut ring, &mut slots, *job)?;
+                        in_flight += 1;
+                    }
+                    Hashed::Done(index, result, buffer) => {
+                        r[index] = Some(result);
+                        buffers.push(buffer);
+                    }
+                }
+            }
+            if in_flight == 0 {
+                continue;
+            }
+
+            match ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+
+            let completed = ring
+                .completion()
+                .map(|cqe| (cqe.user_data(), cqe.result()))
+                .collect::<Vec<_>>();
+
+            for (slot, result) in completed {
+                let slot = usize::try_from(slot).unwrap_or(usize::MAX);
+                let Some(job) = slots.get_mut(slot).and_then(Option::take) else {
+                    continue;
+                };
+                in_flight -= 1;
+                let Ok(n) = usize::try_from(result) else {
+                    r[job.index] = Some(Err(Error::from_raw_os_error(-result).into()));
+                    buffers.push(job.buffer);
+                    continue;
+                };
+                hashing += 1;
+                let tx = tx.clone();
+                let file = files[job.index].as_ref();
+                let work = move || {
+                    let _ = tx.send(hash(job, n, file, process));
+                };
+                if parallel {
+                    scope.spawn(move |_| work());
+                } else {
+                    work();
+                }
+            }
+        }
+    });
+
+    // The kernel may still write into the buffers of reads in flight if the ring failed, so wait
+    // for them and only leak the jobs of reads that could not be waited for
+    if result.is_err() {
+        drain(&mut ring, &mut slots);
+    }
+    if slots.iter().any(Option::is_some) {
+        std::mem::forget(slots);
+    }
+    result
+}
+
+/// Wait for the reads in flight and release their jobs, until none is left or the ring fails
+fn drain(ring: &mut IoUring, slots: &mut [Option<Job>]) {
+    while slots.iter().any(Option::is_some) {
+        match ring.submit_and_wait(1) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => return,
+        }
+        for cqe in ring.completion() {
+            if let Some(slot) = usize::try_from(cqe.user_data())
+                .ok()
+                .and_then(|slot| slots.get_mut(slot))
+            {
+                slot.take();
+            }
+        }
+    }
+}
+
+/// Hash a completed read of `n` bytes, or finish the file at its end
+fn hash<T: Process>(mut job: Job, n: usize, file: &Path, process: &T) -> Hashed<T::Output> {
+    if n == 0 {
+        let result = job
+            .hasher
+            .finalize(file)
+            .and_then(|hashes| process.finish(file, &hashes));
+        Hashed::Done(job.index, result, job.buffer)
+    } else {
+        job.hasher.update(&job.buffer[..n]);
+        job.offset += n as u64;
+        Hashed::Read(Box::new(job))
+    }
+}
+
+/**
+Wait for a hashed job
+
+Runs pending Rayon tasks while waiting on a Rayon thread, so hashing cannot starve behind the
+waiting thread in a small pool.
+*/
+fn wait<O>(rx: &Receiver<Hashed<O>>) -> Option<Hashed<O>> {
+    loop {
+        if let Ok(hashed) = rx.try_recv() {
+            return Some(hashed);
+        }
+        match rayon::yield_now() {
+            None => return rx.recv().ok(),
+            Some(Yield::Executed) => {}
+            Some(Yield::Idle) => std::thread::yield_now(),
+        }
+    }
+}
+
+/// Queue the next read of a job into a free slot
+fn submit(ring: &mut IoUring, slots: &mut [Option<Job>], mut job: Job) -> std::io::Result<()> {
+    let slot = slots
+        .iter()
+        .position(Option::is_none)
+        .ok_or_else(|| Error::other("no free io_uring slot"))?;
+    let entry = opcode::Read::new(
+        types::Fd(job.file.as_raw_fd()),
+        job.buffer.as_mut_ptr(),
+        u32::try_from(job.buffer.len()).unwrap_or(u32::MAX),
+    )
+    .offset(job.offset)
+    .build()
+    .user_data(slot as u64);
+
+    // SAFETY: the buffer and file descriptor stay alive until the read completes because the
+    // job is kept in its slot until then, and the slots are drained or leaked if the ring fails.
+    unsafe { ring.submission().push(&entry) }.map_err(|e| Error::other(e.to_string()))?;
+    slots[slot] = Some(job);
+    Ok(())
+}
@@ -0,0 +1,336 @@
+use {
+    crate::{Algorithm, Options, diff::walk, json::json_string},
+    anyhow::{Result, anyhow, bail},
+    std::{
+        collections::{BTreeSet, HashMap},
+        ffi::{CString, c_char, c_int},
+        fs::File,
+        io::Read,
+        os::{
+            fd::{FromRawFd, OwnedFd},
+            unix::ffi::OsStrExt,
+        },
+        path::{Path, PathBuf},
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+const IN_CLOEXEC: c_int = 0o2_000_000;
+const IN_CLOSE_WRITE: u32 = 0x0000_0008;
+const IN_MOVED_TO: u32 = 0x0000_0080;
+const IN_CREATE: u32 = 0x0000_0100;
+const IN_Q_OVERFLOW: u32 = 0x0000_4000;
+const IN_IGNORED: u32 = 0x0000_8000;
+const IN_ONLYDIR: u32 = 0x0100_0000;
+const IN_ISDIR: u32 = 0x4000_0000;
+
+/// Size of the fixed part of `struct inotify_event` (`wd`, `mask`, `cookie`, `len`)
+const EVENT_SIZE: usize = 16;
+
+unsafe extern "C" {
+    fn inotify_init1(flags: c_int) -> c_int;
+    fn inotify_add_watch(fd: c_int, pathname: *const c_char, mask: u32) -> c_int;
+}
+
+/// What a [`Watcher`] did with a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Hashed a new file and saved its hash file(s)
+    Hashed,
+
+    /// Re-verified a file against its existing hash file(s)
+    Verified,
+
+    /// Failed to watch a new directory (or list the files of a moved one); only reported with its
+    /// error, which names the directory
+    Watched,
+
+    /// Missed changes because the inotify queue overflowed; only reported with its error, and the
+    /// file is empty
+    Overflowed,
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Action::Hashed => "hashed",
+            Action::Verified => "verified",
+            Action::Watched => "watched",
+            Action::Overflowed => "overflow",
+        })
+    }
+}
+
+/// File processed by a [`Watcher`]
+#[derive(Debug)]
+pub struct WatchEvent {
+    /// Unix time
+    pub time: u64,
+
+    /// File
+    pub file: PathBuf,
+
+    /// Action
+    pub action: Action,
+
+    /// Result of [`Options::process_file`]
+    pub result: Result<String>,
+
+    /// `LABEL:digest` hashes of the file (empty if it could not be hashed)
+    pub hashes: Vec<String>,
+}
+
+impl WatchEvent {
+    /// Create an event for an error
+    fn error(file: PathBuf, action: Action, e: anyhow::Error) -> WatchEvent {
+        WatchEvent {
+            time: now(),
+            file,
+            action,
+            result: Err(e),
+            hashes: vec![],
+        }
+    }
+
+    /**
+    Encode as a JSON object
+
+    Hashed files have their `hashes`, verified files their `status`, and failures an `error`;
+    queue overflows have no `path`:
+
+    ```json
+    {"time":1700000000,"event":"hashed","path":"drop/file","hashes":["..."]}
+    {"time":1700000000,"event":"verified","path":"drop/file","status":"OK"}
+    {"time":1700000000,"event":"error","path":"drop/file","error":"..."}
+    {"time":1700000000,"event":"overflow","error":"..."}
+    ```
+    */
+    #[must_use]
+    pub fn json(&self) -> String {
+        let path = self.file.display().to_string();
+        let (event, detail) = match (&self.result, self.action) {
+            (Err(e), action) => (
+                if action == Action::Overflowed {
+                    "overflow"
+                } else {
+                    "error"
+                },
+                format!("\"error\":{}", json_string(&e.to_string())),
+            ),
+            (Ok(_), Action::Hashed) => (
+                "hashed",
+                format!(
+                    "\"hashes\":[{}]",
+                    self.hashes
+                        .iter()
+                        .map(|hash| json_string(hash))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+            ),
+            (Ok(r), _) => (
+                "verified",
+                format!(
+                    "\"status\":{}",
+                    json_string(r.strip_prefix(&format!("{path}: ")).unwrap_or(r))
+                ),
+            ),
+        };
+        let path = if self.action == Action::Overflowed {
+            String::new()
+        } else {
+            format!(",\"path\":{}", json_string(&path))
+        };
+        format!(
+            "{{\"time\":{},\"event\":{}{path},{detail}}}",
+            self.time,
+            json_string(event),
+        )
+    }
+}
+
+/**
+Watch directory trees via Linux inotify and process files as they are written
+
+Every file that is closed after writing or moved into a watched directory is processed with
+[`Options::process_file`]: a new file is hashed and its hash file(s) saved, while a file that
+already has hash file(s) is re-verified.
+Subdirectories are watched too, including ones created or moved in later (the files of a moved
+directory are processed right away).
+Hash files, Bao outboards (`.obao`), and minisign and SSH signatures (`.minisig`, `.sig`) are
+ignored, as are dot files (e.g. temporary files of `rsync`, which are processed once renamed).
+New directories that cannot be watched are reported as [`Action::Watched`] errors and a queue
+overflow as an [`Action::Overflowed`] error.
+*/
+#[derive(Debug)]
+pub struct Watcher {
+    inotify: File,
+    dirs: HashMap<c_int, PathBuf>,
+}
+
+impl Watcher {
+    /**
+    Start watching directory trees
+
+    # Errors
+
+    Returns an error if inotify is not available or not able to watch a directory
+    */
+    pub fn new<P: AsRef<Path>>(dirs: &[P]) -> Result<Watcher> {
+        // SAFETY: `inotify_init1` takes no pointers.
+        let fd = unsafe { inotify_init1(IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        // SAFETY: `fd` is a new file descriptor owned by nothing else.
+        let inotify = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+        let mut r = Watcher {
+            inotify,
+            dirs: HashMap::new(),
+        };
+        for dir in dirs {
+            r.add_tree(dir.as_ref())?;
+        }
+        Ok(r)
+    }
+
+    /**
+    Process files as they change and pass each event to `f` until it returns `false`
+
+    # Errors
+
+    Returns an error if not able to read inotify events
+    */
+    pub fn run<F: FnMut(WatchEvent) -> bool>(&mut self, options: &Options, mut f: F) -> Result<()> {
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let n = self.inotify.read(&mut buffer)?;
+            let mut files = vec![];
+            let mut i = 0;
+            while i + EVENT_SIZE <= n {
+                let field = |at: usize| {
+                    u32::from_ne_bytes(buffer[i + at..i + at + 4].try_into().unwrap_or_default())
+                };
+                let (wd, mask, len) = (field(0).cast_signed(), field(4), field(12) as usize);
+                let name = &buffer[i + EVENT_SIZE..(i + EVENT_SIZE + len).min(n)];
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                i += EVENT_SIZE + len;
+
+                if mask & IN_Q_OVERFLOW != 0 {
+                    let e = anyhow!("too many events, some changes were missed");
+                    if !f(WatchEvent::error(PathBuf::new(), Action::Overflowed, e)) {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                if mask & IN_IGNORED != 0 {
+                    self.dirs.remove(&wd);
+                    continue;
+                }
+                let Some(dir) = self.dirs.get(&wd) else {
+                    continue;
+                };
+                let path = dir.join(std::ffi::OsStr::from_bytes(name));
+                if mask & IN_ISDIR != 0 {
+                    if mask & (IN_CREATE | IN_MOVED_TO) == 0 {
+                        continue;
+                    }
+                    // Files of a moved directory are complete, but those of a new one may still
+                    // be written, so they are left to their own events
+                    let walked = self.add_tree(&path).and_then(|()| {
+                        if mask & IN_MOVED_TO == 0 {
+                            return Ok(BTreeSet::new());
+                        }
+                        walk(&path)
+                    });
+                    match walked {
+                        Ok(walked) => files.extend(walked.into_iter().map(|file| path.join(file))),
+                        Err(e) => {
+                            if !f(WatchEvent::error(path, Action::Watched, e)) {
+                                return Ok(());
+                            }
+                        }
+                    }
+                } else if mask & (IN_CLOSE_WRITE | IN_MOVED_TO) != 0 {
+                    files.push(path);
+                }
+            }
+
+            for file in files {
+                if ignored(&file) {
+                    continue;
+                }
+                let action = if options.hash.algorithms().into_iter().all(|algorithm| {
+                    Path::new(&format!("{}.{}", file.display(), algorithm.extension())).exists()
+                }) {
+                    Action::Verified
+                } else {
+                    Action::Hashed
+                };
+                let (result, hashes) = match options.process_file_hashes(&file) {
+                    Ok((result, hashes)) => (
+                        Ok(result),
+                        hashes.into_iter().map(|(_, hash)| hash).collect(),
+                    ),
+                    Err(e) => (Err(e), vec![]),
+                };
+                if !f(WatchEvent {
+                    time: now(),
+                    file,
+                    action,
+                    result,
+                    hashes,
+                }) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Watch a directory and its subdirectories
+    fn add_tree(&mut self, dir: &Path) -> Result<()> {
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let path = CString::new(dir.as_os_str().as_bytes())?;
+            // SAFETY: `path` is a NUL-terminated string that outlives the call.
+            let wd = unsafe {
+                inotify_add_watch(
+                    std::os::fd::AsRawFd::as_raw_fd(&self.inotify),
+                    path.as_ptr(),
+                    IN_CLOSE_WRITE | IN_MOVED_TO | IN_CREATE | IN_ONLYDIR,
+                )
+            };
+            if wd < 0 {
+                bail!("{}: {}", dir.display(), std::io::Error::last_os_error());
+            }
+            let with_dir = |e: std::io::Error| anyhow!("{}: {e}", dir.display());
+            for entry in std::fs::read_dir(&dir).map_err(with_dir)? {
+                let entry = entry.map_err(with_dir)?;
+                if entry.file_type().map_err(with_dir)?.is_dir() {
+                    dirs.push(entry.path());
+                }
+            }
+            self.dirs.insert(wd, dir);
+        }
+        Ok(())
+    }
+}
+
+/// Check whether a file is a dot file, hash file, outboard, or signature that is not processed
+fn ignored(file: &Path) -> bool {
+    file.file_name()
+        .is_none_or(|name| name.as_bytes().starts_with(b"."))
+        || file.extension().is_some_and(|extension| {
+            ["obao", "minisig", "sig"].iter().any(|x| extension == *x)
+                || [Algorithm::Blake3, Algorithm::Sha256, Algorithm::Sha512]
+                    .iter()
+                    .any(|algorithm| extension == algorithm.extension())
+        })
+}
+
+/// Get the current Unix time
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}